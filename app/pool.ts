@@ -0,0 +1,32 @@
+import { BN } from "@coral-xyz/anchor";
+import { PublicKey } from "@solana/web3.js";
+
+const Q192 = new BN(1).shln(192);
+
+// sqrt(1/price) in x96 is 2^192 / sqrt_price_x96, rounded down like the program does
+export function invertSqrtPriceX96(sqrtPriceX96: BN): BN {
+  if (sqrtPriceX96.isZero()) {
+    throw new Error("InvalidSqrtPrice");
+  }
+  const inverted = Q192.div(sqrtPriceX96);
+  if (inverted.bitLength() > 128) {
+    throw new Error("ArithmeticOverflow");
+  }
+  return inverted;
+}
+
+// pools are always created with token_mint_0 < token_mint_1, a price quoted as
+// mintB per mintA is inverted when the mints have to be swapped
+export function canonicalMintOrder(
+  mintA: PublicKey,
+  mintB: PublicKey,
+  sqrtPriceX96: BN
+): { mint0: PublicKey; mint1: PublicKey; sqrtPriceX96: BN; swapped: boolean } {
+  const order = Buffer.compare(mintA.toBuffer(), mintB.toBuffer());
+  if (order === 0) {
+    throw new Error("DuplicateMint");
+  }
+  return order < 0
+    ? { mint0: mintA, mint1: mintB, sqrtPriceX96, swapped: false }
+    : { mint0: mintB, mint1: mintA, sqrtPriceX96: invertSqrtPriceX96(sqrtPriceX96), swapped: true };
+}
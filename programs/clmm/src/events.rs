@@ -0,0 +1,153 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct PoolInitialized{
+    pub pool : Pubkey,
+    pub token_mint_0 : Pubkey,
+    pub token_mint_1 : Pubkey,
+    pub tick_spacing : i32,
+    pub sqrt_price_x96 : u128,
+    pub tick : i32,
+    pub fee_rate : u32
+}
+
+#[event]
+pub struct PositionOpened{
+    pub pool : Pubkey,
+    pub position : Pubkey,
+    pub owner : Pubkey,
+    pub tick_lower : i32,
+    pub tick_upper : i32,
+    pub liquidity : u128,
+    pub amount_0 : u64,
+    pub amount_1 : u64
+}
+
+#[event]
+pub struct LiquidityIncreased{
+    pub pool : Pubkey,
+    pub position : Pubkey,
+    pub liquidity : u128,
+    pub amount_0 : u64,
+    pub amount_1 : u64
+}
+
+#[event]
+pub struct LiquidityDecreased{
+    pub pool : Pubkey,
+    pub position : Pubkey,
+    pub liquidity : u128,
+    pub amount_0 : u64,
+    pub amount_1 : u64
+}
+
+//amounts include any fees that were still owed to the position
+#[event]
+pub struct PositionClosed{
+    pub pool : Pubkey,
+    pub position : Pubkey,
+    pub owner : Pubkey,
+    pub liquidity : u128,
+    pub amount_0 : u64,
+    pub amount_1 : u64
+}
+
+//sqrt price, tick and liquidity are the pool state after the swap
+#[event]
+pub struct SwapEvent{
+    pub pool : Pubkey,
+    pub sender : Pubkey,
+    pub swap_token_0_for_1 : bool,
+    pub amount_in : u64,
+    pub amount_out : u64,
+    pub fee_amount : u64,
+    pub referral_fee : u64, //paid to the referrer on top of fee_amount, in the mint it was taken from
+    pub sqrt_price_x96 : u128,
+    pub tick : i32,
+    pub liquidity : u128
+}
+
+//paid is what actually came back on top of the loan, all of it goes to the LPs
+#[event]
+pub struct FlashLoan{
+    pub pool : Pubkey,
+    pub sender : Pubkey,
+    pub amount_0 : u64,
+    pub amount_1 : u64,
+    pub paid_0 : u64,
+    pub paid_1 : u64
+}
+
+//amounts are what the owner received, the bounty is in the token the position was withdrawn in
+#[event]
+pub struct PositionSettled{
+    pub pool : Pubkey,
+    pub position : Pubkey,
+    pub owner : Pubkey,
+    pub keeper : Pubkey,
+    pub liquidity : u128,
+    pub amount_0 : u64,
+    pub amount_1 : u64,
+    pub keeper_bounty : u64
+}
+
+//dust is what could not be deposited and went back to the user
+#[event]
+pub struct ZapInEvent{
+    pub pool : Pubkey,
+    pub position : Pubkey,
+    pub owner : Pubkey,
+    pub input_token_0 : bool,
+    pub amount_in : u64,
+    pub swap_amount_in : u64,
+    pub swap_amount_out : u64,
+    pub liquidity : u128,
+    pub dust_0 : u64,
+    pub dust_1 : u64
+}
+
+//amount_0/amount_1 are what the position gave up, amount_out the single token the owner got
+#[event]
+pub struct ZapOutEvent{
+    pub pool : Pubkey,
+    pub position : Pubkey,
+    pub owner : Pubkey,
+    pub output_token_0 : bool,
+    pub liquidity : u128,
+    pub amount_0 : u64,
+    pub amount_1 : u64,
+    pub swap_amount_in : u64,
+    pub amount_out : u64,
+    pub closed : bool
+}
+
+#[event]
+pub struct LimitOrderPlaced{
+    pub pool : Pubkey,
+    pub order : Pubkey,
+    pub owner : Pubkey,
+    pub tick : i32,
+    pub sell_token_0 : bool,
+    pub liquidity : u128,
+    pub amount : u64 //of the token being sold
+}
+
+//filled orders pay the bought token, unfilled ones give back what is left of the order
+#[event]
+pub struct LimitOrderClaimed{
+    pub pool : Pubkey,
+    pub order : Pubkey,
+    pub owner : Pubkey,
+    pub filled : bool,
+    pub amount_0 : u64,
+    pub amount_1 : u64
+}
+
+#[event]
+pub struct FeesCollected{
+    pub pool : Pubkey,
+    pub position : Pubkey,
+    pub owner : Pubkey,
+    pub amount_0 : u64,
+    pub amount_1 : u64
+}
@@ -0,0 +1,24 @@
+use anchor_lang::prelude::*;
+use crate::states::GlobalConfig;
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info>{
+    pub pending_admin : Signer<'info>,
+    #[account(
+        mut,
+        has_one = pending_admin @ErrorCode::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+}
+
+impl <'info> AcceptAdmin<'info>{
+    pub fn new(&mut self)->Result<()>{
+        let config = &mut self.config;
+        config.admin = config.pending_admin;
+        config.pending_admin = Pubkey::default();
+        Ok(())
+    }
+}
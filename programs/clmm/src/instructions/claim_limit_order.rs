@@ -0,0 +1,157 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{
+    states::{modify_limit_order, LimitOrder, ObservationState, Pool, TickArray, POOL_STATUS_WITHDRAW_PAUSED},
+    utils::{get_amount_for_liquidity, get_sqrt_price_from_tick, transfer_from_vault_to_user}
+};
+use crate::utils::ErrorCode;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimLimitOrder<'info>{
+    #[account(mut)]
+    pub payer : Signer<'info>,
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
+    pub pool : Account<'info,Pool>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(limit_order.tick, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        close = payer,
+        has_one = pool @ErrorCode::InvalidLimitOrder,
+        constraint = limit_order.owner == payer.key() @ErrorCode::Unauthorized,
+        seeds = [
+            b"limit_order",
+            payer.key().as_ref(),
+            pool.key().as_ref(),
+            &limit_order.tick.to_le_bytes()
+        ],
+        bump = limit_order.bump
+    )]
+    pub limit_order : Account<'info, LimitOrder>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
+    )]
+    pub user_token_0 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
+    )]
+    pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_0 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_1 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>,
+}
+
+impl <'info> ClaimLimitOrder<'info>{
+    //a filled order pays out the bought token no matter where the price is now, an order that
+    //was not filled yet is cancelled and gives back whatever its range holds at the current price
+    //either way the order account is closed
+    pub fn new(&mut self)->Result<(u64,u64,bool)>{
+        let pool = &mut self.pool;
+        require!(!pool.is_paused(POOL_STATUS_WITHDRAW_PAUSED), ErrorCode::OperationPaused);
+        pool.lock()?;
+        //written before the liquidity changes, so the last interval is weighted with the old value
+        ObservationState::record(&self.observation_state, Clock::get()?.unix_timestamp as u32, pool.current_tick, pool.global_liquidity)?;
+
+        let order = &mut self.limit_order;
+        let tick_lower = order.tick;
+        let tick_upper = order.tick + pool.tick_spacing;
+        let liquidity = order.liquidity;
+        let mut tick_array = self.tick_array.load_mut()?;
+        let fill_tick_info = tick_array
+            .get_tick_info_mutable(order.fill_tick(pool.tick_spacing), pool.tick_spacing)?;
+        let filled = order.is_filled(fill_tick_info);
+
+        let (amount_0, amount_1) = if filled {
+            if order.sell_token_0 {
+                fill_tick_info.limit_order_filled_0 = fill_tick_info.limit_order_filled_0
+                    .checked_sub(liquidity)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+            } else {
+                fill_tick_info.limit_order_filled_1 = fill_tick_info.limit_order_filled_1
+                    .checked_sub(liquidity)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+            }
+            //priced as if the price sat just past the far end of the range
+            let lower_sqrt_price_x96 = get_sqrt_price_from_tick(tick_lower)?;
+            let upper_sqrt_price_x96 = get_sqrt_price_from_tick(tick_upper)?;
+            let sqrt_price_x96 = if order.sell_token_0 {
+                upper_sqrt_price_x96
+            } else {
+                lower_sqrt_price_x96 - 1
+            };
+            get_amount_for_liquidity(sqrt_price_x96, lower_sqrt_price_x96, upper_sqrt_price_x96, liquidity)?
+        } else {
+            let amounts = get_amount_for_liquidity(
+                pool.sqrt_price_x96,
+                get_sqrt_price_from_tick(tick_lower)?,
+                get_sqrt_price_from_tick(tick_upper)?,
+                liquidity
+            )?;
+            modify_limit_order(pool, order, &mut tick_array, -(liquidity as i128), None)?;
+            amounts
+        };
+
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_0,
+            &self.user_token_0,
+            &self.token_mint_0,
+            &self.token_program_0,
+            amount_0
+        )?;
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_1,
+            &self.user_token_1,
+            &self.token_mint_1,
+            &self.token_program_1,
+            amount_1
+        )?;
+
+        self.pool.unlock();
+        Ok((amount_0, amount_1, filled))
+    }
+}
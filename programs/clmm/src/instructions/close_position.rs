@@ -1,14 +1,19 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{ token::{self,  Transfer}, token_interface::{Mint, TokenAccount, TokenInterface}};
-use crate::{states::{Pool, Position, TickArray}, utils::{get_amount_for_liquidity, get_sqrt_price_from_tick}};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{states::{modify_position, ObservationState, Pool, Position, TickArray, POOL_STATUS_WITHDRAW_PAUSED}, utils::{get_amount_for_liquidity, get_sqrt_price_from_tick, transfer_from_vault_to_user}};
 use crate::utils::ErrorCode;
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(lower_tick: i32, upper_tick: i32, tick_array_lower_start_index: i32, tick_array_upper_start_index: i32)]
 pub struct  ClosePosition<'info>{
     #[account(mut)]
     pub owner : Signer<'info>,
-    #[account(mut)]
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
     pub pool : Account<'info, Pool>,
     #[account(
         mut,
@@ -19,7 +24,7 @@ pub struct  ClosePosition<'info>{
         ],
         bump
     )]
-    pub lower_tick_array : Account<'info,TickArray>,
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
     #[account(
         mut,
         seeds = [
@@ -29,7 +34,7 @@ pub struct  ClosePosition<'info>{
         ],
         bump
     )]
-    pub upper_tick_array : Account<'info,TickArray>,
+    pub upper_tick_array : AccountLoader<'info, TickArray>,
     #[account(
         mut,
         close = owner,
@@ -43,31 +48,50 @@ pub struct  ClosePosition<'info>{
         bump = position.bump
     )]
     pub position : Account<'info,Position>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
         #[account(
         mut,
-        token::mint = token_mint_0
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
     )]
     pub user_token_0 : InterfaceAccount<'info, TokenAccount>,
     #[account(
         mut,
-        token::mint = token_mint_1
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
     )]
     pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
     #[account(
         mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault,
         token::mint = token_mint_0
     )]
     pub pool_token_0 : InterfaceAccount<'info, TokenAccount>,
      #[account(
         mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault,
         token::mint = token_mint_1
     )]
     pub pool_token_1 : InterfaceAccount<'info, TokenAccount>,
-
+    #[account(
+        mint::token_program = token_program_0
+    )]
     pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
     pub token_mint_1 : InterfaceAccount<'info,Mint>,
     pub system_program : Program<'info,System>,
-    pub token_program : Interface<'info , TokenInterface>
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>
   
 }
 impl <'info> ClosePosition <'info>{
@@ -81,6 +105,8 @@ impl <'info> ClosePosition <'info>{
         )->Result<(u64,u64)>{
             let pool = &mut self.pool;
             let position = &mut self.position;
+            require!(!pool.is_paused(POOL_STATUS_WITHDRAW_PAUSED), ErrorCode::OperationPaused);
+            pool.lock()?;
 
             let liquidity_to_remove = position.liquidity;
             require!(liquidity_to_remove > 0, ErrorCode::NoLiquidityToRemove);
@@ -91,58 +117,44 @@ impl <'info> ClosePosition <'info>{
                 get_sqrt_price_from_tick(uppar_tick)?,
                 liquidity_to_remove,
             )?;
-            let lower_tick_array = &mut self.lower_tick_array;
-            let uppar_tick_array = &mut self.upper_tick_array;
-
-            let lower_tick_info = 
-                lower_tick_array.get_tick_info_mutable(lower_tick,pool.tick_spacing)?;
-            lower_tick_info.update_liquidity(-(liquidity_to_remove as i128), true)?;
-
-            let uppar_tick_info = 
-                uppar_tick_array.get_tick_info_mutable(uppar_tick,pool.tick_spacing)?;
-            uppar_tick_info.update_liquidity(-(liquidity_to_remove as i128), false)?;
+            let (mut lower_tick_array, mut uppar_tick_array) =
+                TickArray::load_range_mut(&self.lower_tick_array, &self.upper_tick_array)?;
+            //written before the liquidity changes, so the last interval is weighted with the old value
+            ObservationState::record(&self.observation_state, Clock::get()?.unix_timestamp as u32, pool.current_tick, pool.global_liquidity)?;
+            modify_position(
+                pool,
+                position,
+                &mut lower_tick_array,
+                uppar_tick_array.as_deref_mut(),
+                -(liquidity_to_remove as i128),
+                None
+            )?;
 
-            pool.global_liquidity = pool
-                .global_liquidity
-                .checked_sub(liquidity_to_remove)
+            //the account is closed, so any uncollected fees are paid out with the liquidity
+            let amount_0 = amount_0
+                .checked_add(position.tokens_owed_0)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            let amount_1 = amount_1
+                .checked_add(position.tokens_owed_1)
                 .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-            let pool_seeds = &[
-                b"pool".as_ref(),
-                pool.token_mint_0.as_ref(),
-                pool.token_mint_1.as_ref(),
-                &[pool.bump],
-            ];
-            let signer_seeds = &[&pool_seeds[..]];
-
-            if amount_0>0 {
-                token::transfer(
-                    CpiContext::new_with_signer(
-                        self.token_program.to_account_info(),
-                        Transfer { 
-                            from : self.pool_token_0.to_account_info(),
-                            to : self.user_token_0.to_account_info(),
-                            authority : pool.to_account_info()
-                         },
-                         signer_seeds
-                    ),
-                    amount_0
-                )?;
-            };
-            if amount_1 > 1 {
-                token::transfer(
-                    CpiContext::new_with_signer(
-                        self.token_program.to_account_info(),
-                        Transfer{
-                            from : self.pool_token_1.to_account_info(),
-                            to : self.user_token_1.to_account_info(),
-                            authority : pool.to_account_info()
-                        },
-                        signer_seeds
-                    ),
-                    amount_1
-                )?;
-            };
+            transfer_from_vault_to_user(
+                &self.pool,
+                &self.pool_token_0,
+                &self.user_token_0,
+                &self.token_mint_0,
+                &self.token_program_0,
+                amount_0
+            )?;
+            transfer_from_vault_to_user(
+                &self.pool,
+                &self.pool_token_1,
+                &self.user_token_1,
+                &self.token_mint_1,
+                &self.token_program_1,
+                amount_1
+            )?;
+            self.pool.unlock();
             Ok((amount_0,amount_1))
 
         }
@@ -0,0 +1,36 @@
+use anchor_lang::prelude::*;
+use crate::states::{Pool, TickArray};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+#[instruction(tick_array_start_index : i32)]
+pub struct CloseTickArray<'info>{
+    //anyone can crank this, the rent always goes back to the funder
+    pub payer : Signer<'info>,
+    /// CHECK: only receives lamports, checked against tick_array.funder
+    #[account(mut)]
+    pub funder : UncheckedAccount<'info>,
+    pub pool : Account<'info, Pool>,
+    #[account(
+        mut,
+        close = funder,
+        has_one = pool @ErrorCode::InvalidTickArrayAccount,
+        has_one = funder @ErrorCode::Unauthorized,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &tick_array_start_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub tick_array : AccountLoader<'info, TickArray>,
+}
+
+impl <'info> CloseTickArray<'info>{
+    pub fn new(&mut self)->Result<()>{
+        // there is no tick bitmap, a tick is only referenced through its TickInfo
+        // so once every tick is back to zero gross liquidity nothing points at the array
+        require!(self.tick_array.load()?.is_empty(), ErrorCode::TickArrayNotEmpty);
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{states::{modify_position, Pool, Position, TickArray, POOL_STATUS_COLLECT_FEE_PAUSED}, utils::transfer_from_vault_to_user};
+use crate::utils::ErrorCode;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CollectFees<'info>{
+    pub payer : Signer<'info>,
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
+    pub pool : Account<'info,Pool>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_lower, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_uppar, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub uppar_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        constraint = position.pool == pool.key() @ErrorCode::InvalidTickRange,
+        constraint  = position.owner == payer.key() @ErrorCode::Unauthorized
+    )]
+    pub position : Account<'info ,Position>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
+    )]
+    pub user_token_0 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
+    )]
+    pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault,
+        token::mint = token_mint_0
+    )]
+    pub pool_token_0 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault,
+        token::mint = token_mint_1
+    )]
+    pub pool_token_1 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>
+}
+
+impl <'info> CollectFees<'info>{
+    pub fn new(&mut self)->Result<(u64,u64)>{
+        let pool = &mut self.pool;
+        let position = &mut self.position;
+        require!(!pool.is_paused(POOL_STATUS_COLLECT_FEE_PAUSED), ErrorCode::OperationPaused);
+        pool.lock()?;
+
+        //settle what the position earned since it was last touched
+        if position.liquidity > 0 {
+            let (mut lower_tick_array, mut upper_tick_array) =
+                TickArray::load_range_mut(&self.lower_tick_array, &self.uppar_tick_array)?;
+            modify_position(
+                pool,
+                position,
+                &mut lower_tick_array,
+                upper_tick_array.as_deref_mut(),
+                0,
+                None
+            )?;
+        }
+        let amount_0 = position.tokens_owed_0;
+        let amount_1 = position.tokens_owed_1;
+        position.tokens_owed_0 = 0;
+        position.tokens_owed_1 = 0;
+
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_0,
+            &self.user_token_0,
+            &self.token_mint_0,
+            &self.token_program_0,
+            amount_0
+        )?;
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_1,
+            &self.user_token_1,
+            &self.token_mint_1,
+            &self.token_program_1,
+            amount_1
+        )?;
+        self.pool.unlock();
+        Ok((amount_0,amount_1))
+    }
+}
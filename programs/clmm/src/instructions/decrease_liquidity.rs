@@ -1,56 +1,89 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{ token::{self,  Transfer}, token_interface::{Mint, TokenAccount, TokenInterface}};
-use crate::{states::{Pool, Position, TickArray}, utils::{get_amount_for_liquidity, get_sqrt_price_from_tick}};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{states::{modify_position, ObservationState, Pool, Position, TickArray, POOL_STATUS_WITHDRAW_PAUSED}, utils::{get_amount_for_liquidity, get_sqrt_price_from_tick, transfer_from_vault_to_user}};
 use crate::utils::ErrorCode;
+#[event_cpi]
 #[derive(Accounts)]
 pub struct DecreaseLiquidity<'info>{
     #[account(mut)]
     pub payer : Signer<'info>,
     #[account(
         mut ,
-        has_one = token_mint_0,
-        has_one = token_mint_1
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
     )]
     pub pool : Account<'info,Pool>,
     #[account(
-        mut
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_lower, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
     )]
-    pub lower_tick_array : Account<'info,TickArray>,
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
     #[account(
-        mut
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_uppar, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
     )]
-    pub uppar_tick_array : Account<'info, TickArray>,
+    pub uppar_tick_array : AccountLoader<'info, TickArray>,
     #[account(
+        mut,
         constraint = position.pool == pool.key() @ErrorCode::InvalidTickRange,
         constraint  = position.owner == payer.key() @ErrorCode::Unauthorized
     )]
     pub position : Account<'info ,Position>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
     
     #[account(
         mut,
-        token::mint = token_mint_0
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
     )]
     pub user_token_0 : InterfaceAccount<'info, TokenAccount>,
     #[account(
         mut,
-        token::mint = token_mint_1
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
     )]
     pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
     #[account(
         mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault,
         token::mint = token_mint_0
     )]
     pub pool_token_0 : InterfaceAccount<'info, TokenAccount>,
      #[account(
         mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault,
         token::mint = token_mint_1
     )]
     pub pool_token_1 : InterfaceAccount<'info, TokenAccount>,
-    
+    #[account(
+        mint::token_program = token_program_0
+    )]
     pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
     pub token_mint_1 : InterfaceAccount<'info,Mint>,
     pub system_program : Program<'info,System>,
-    pub token_program : Interface<'info , TokenInterface>
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>
 }
 
 impl <'info> DecreaseLiquidity<'info>{
@@ -62,6 +95,8 @@ impl <'info> DecreaseLiquidity<'info>{
     )->Result<(u64,u64)>{
         let pool = &mut self.pool;
         let position = &mut self.position;
+        require!(!pool.is_paused(POOL_STATUS_WITHDRAW_PAUSED), ErrorCode::OperationPaused);
+        pool.lock()?;
         require!(
             lower_tick < uppar_tick
               &&lower_tick % pool.tick_spacing == 0
@@ -70,21 +105,11 @@ impl <'info> DecreaseLiquidity<'info>{
         );
         require!(liquidity_amount > 0 , ErrorCode::InsufficentAmount);
         require!(
-           pool.current_tick >= lower_tick && pool.current_tick < uppar_tick,
-           ErrorCode::MintRangeMustCoverCurrentPrice
+            position.tick_lower == lower_tick && position.tick_uppar == uppar_tick,
+            ErrorCode::InvalidPositionRange
         );
-        let lower_tick_array = &mut self.lower_tick_array;
-         let upper_tick_array = &mut self.uppar_tick_array;
-
-        let lower_tick_info =
-            lower_tick_array.get_tick_info_mutable(lower_tick, pool.tick_spacing)?;
-        let upper_tick_info =
-            upper_tick_array.get_tick_info_mutable(uppar_tick, pool.tick_spacing)?;
-
-        lower_tick_info.update_liquidity(liquidity_amount as i128, true)?;
-        upper_tick_info.update_liquidity(liquidity_amount as i128, false)?;
-
-        position.liquidity = position.liquidity.checked_sub(liquidity_amount as u128).ok_or(ErrorCode::ArithmeticOverflow)?;
+        let (mut lower_tick_array, mut upper_tick_array) =
+            TickArray::load_range_mut(&self.lower_tick_array, &self.uppar_tick_array)?;
 
         let (amount_0, amount_1) = get_amount_for_liquidity(
             pool.sqrt_price_x96,
@@ -92,55 +117,34 @@ impl <'info> DecreaseLiquidity<'info>{
             get_sqrt_price_from_tick(uppar_tick)?,
             liquidity_amount,
         )?;
-
-        pool.global_liquidity = pool
-            .global_liquidity
-            .checked_sub(liquidity_amount)
-            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        //written before the liquidity changes, so the last interval is weighted with the old value
+        ObservationState::record(&self.observation_state, Clock::get()?.unix_timestamp as u32, pool.current_tick, pool.global_liquidity)?;
+        modify_position(
+            pool,
+            position,
+            &mut lower_tick_array,
+            upper_tick_array.as_deref_mut(),
+            -(liquidity_amount as i128),
+            None
+        )?;
         
-        let signer_seeds: &[&[&[u8]]] = &[&[
-                b"pool".as_ref(),
-                pool.token_mint_0.as_ref(),
-                pool.token_mint_1.as_ref(),
-                &pool.tick_spacing.to_le_bytes(),
-                &[pool.bump],
-            ]];
-        if amount_0 > 0 {
-            let signer_seeds: &[&[&[u8]]] = &[&[
-                b"pool".as_ref(),
-                pool.token_mint_0.as_ref(),
-                pool.token_mint_1.as_ref(),
-                &pool.tick_spacing.to_le_bytes(),
-                &[pool.bump],
-            ]];
-            token::transfer(
-                CpiContext::new_with_signer(
-                    self.token_program.to_account_info(),
-                    Transfer{
-                        from : self.pool_token_0.to_account_info(),
-                        to : self.user_token_0.to_account_info(),
-                        authority : pool.to_account_info()
-                    },
-                    signer_seeds
-                ),
-                amount_0
-            )?;
-        };
-
-        if amount_1 > 0 {
-            token::transfer(
-                CpiContext::new_with_signer(
-                    self.token_program.to_account_info(),
-                    Transfer{
-                        from : self.pool_token_1.to_account_info(),
-                        to : self.user_token_1.to_account_info(),
-                        authority : self.user_token_0.to_account_info()
-                    },
-                    signer_seeds
-                ),
-                amount_1
-            )?;
-        };
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_0,
+            &self.user_token_0,
+            &self.token_mint_0,
+            &self.token_program_0,
+            amount_0
+        )?;
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_1,
+            &self.user_token_1,
+            &self.token_mint_1,
+            &self.token_program_1,
+            amount_1
+        )?;
+        self.pool.unlock();
         Ok((amount_0,amount_1))
 
 
@@ -0,0 +1,143 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{
+    states::{Pool, POOL_STATUS_FLASH_PAUSED},
+    utils::{
+        get_fee_growth_delta, get_flash_fee, get_transfer_inverse_fee, invoke_callback,
+        transfer_from_vault_to_user, FlashCallbackArgs, FLASH_CALLBACK_DISCRIMINATOR
+    }
+};
+use crate::utils::ErrorCode;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Flash<'info>{
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
+    pub pool : Account<'info,Pool>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
+    )]
+    pub user_token_0 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
+    )]
+    pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_0 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_1 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
+    pub payer : Signer<'info>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>,
+}
+
+impl <'info> Flash<'info>{
+    //lends amount_0 and amount_1 out of the vaults, then calls the borrower program, which is
+    //remaining_accounts[0], with the rest of remaining_accounts
+    //the vaults have to end up holding at least the loan plus the fee, every token paid on top
+    //of the loan is credited to the LPs in range
+    pub fn new(
+        &mut self,
+        amount_0 : u64,
+        amount_1 : u64,
+        data : Vec<u8>,
+        remaining_accounts : &'info [AccountInfo<'info>]
+    )->Result<(u64,u64)>{
+        require!(!self.pool.is_paused(POOL_STATUS_FLASH_PAUSED), ErrorCode::OperationPaused);
+        self.pool.lock()?;
+        let pool = &self.pool;
+        require!(amount_0 > 0 || amount_1 > 0, ErrorCode::InsufficentAmount);
+        require!(pool.fee_liquidity() > 0, ErrorCode::InsufficientPoolLiquidity);
+        let (callback_program, callback_accounts) = remaining_accounts
+            .split_first()
+            .ok_or(ErrorCode::InvalidCallbackProgram)?;
+
+        let fee_0 = get_flash_fee(amount_0, pool.fee_rate)?;
+        let fee_1 = get_flash_fee(amount_1, pool.fee_rate)?;
+        let amount_0_owed = amount_0
+            .checked_add(fee_0)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let amount_1_owed = amount_1
+            .checked_add(fee_1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let balance_0_before = self.pool_token_0.amount;
+        let balance_1_before = self.pool_token_1.amount;
+
+        transfer_from_vault_to_user(
+            pool,
+            &self.pool_token_0,
+            &self.user_token_0,
+            &self.token_mint_0,
+            &self.token_program_0,
+            amount_0
+        )?;
+        transfer_from_vault_to_user(
+            pool,
+            &self.pool_token_1,
+            &self.user_token_1,
+            &self.token_mint_1,
+            &self.token_program_1,
+            amount_1
+        )?;
+
+        //the pool goes out locked, though a callback calling back into clmm is already refused
+        //by the runtime, see Pool::lock
+        self.pool.exit(&crate::ID)?;
+        invoke_callback(
+            callback_program,
+            callback_accounts,
+            &FLASH_CALLBACK_DISCRIMINATOR,
+            &FlashCallbackArgs{
+                amount_0_owed : amount_0_owed
+                    .checked_add(get_transfer_inverse_fee(&self.token_mint_0, amount_0_owed)?)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?,
+                amount_1_owed : amount_1_owed
+                    .checked_add(get_transfer_inverse_fee(&self.token_mint_1, amount_1_owed)?)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?,
+                data
+            }
+        )?;
+
+        self.pool_token_0.reload()?;
+        self.pool_token_1.reload()?;
+        let paid_0 = self.pool_token_0.amount
+            .checked_sub(balance_0_before)
+            .ok_or(ErrorCode::InsufficientRepayment)?;
+        let paid_1 = self.pool_token_1.amount
+            .checked_sub(balance_1_before)
+            .ok_or(ErrorCode::InsufficientRepayment)?;
+        require!(paid_0 >= fee_0 && paid_1 >= fee_1, ErrorCode::InsufficientRepayment);
+
+        let pool = &mut self.pool;
+        pool.fee_growth_global_0_x64 = pool
+            .fee_growth_global_0_x64
+            .wrapping_add(get_fee_growth_delta(paid_0, pool.fee_liquidity())?);
+        pool.fee_growth_global_1_x64 = pool
+            .fee_growth_global_1_x64
+            .wrapping_add(get_fee_growth_delta(paid_1, pool.fee_liquidity())?);
+        pool.unlock();
+        Ok((paid_0, paid_1))
+    }
+}
@@ -1,59 +1,96 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{ token::{self,  Transfer}, token_interface::{Mint, TokenAccount, TokenInterface}};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 
-use crate::{states::{Pool, Position, TickArray}, utils::{get_amount_for_liquidity, get_sqrt_price_from_tick}};
+use crate::{states::{modify_position, ObservationState, Pool, Position, TickArray, POOL_STATUS_DEPOSIT_PAUSED}, utils::{get_amount_for_liquidity, get_sqrt_price_from_tick, get_transfer_inverse_fee, transfer_from_user_to_vault}};
 use crate::utils::ErrorCode;
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct IncreaseLiquidity<'info>{
     #[account(mut)]
     pub payer : Signer<'info>,
     #[account(
         mut,
-        has_one = token_mint_0,
-        has_one = token_mint_1
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
     )]
     pub pool : Account<'info,Pool>,
 
-    #[account(mut)]
-    pub lower_tick_array : Account<'info,TickArray>,
-    #[account(mut)]
-    pub uppar_tick_array : Account<'info,TickArray>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_lower, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_uppar, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub uppar_tick_array : AccountLoader<'info, TickArray>,
 
     #[account(
+        mut,
         constraint = position.pool == pool.key() @ErrorCode::InvalidRange,
         constraint = position.owner == payer.key() @ErrorCode::Unauthorized
     )]
     pub position : Account<'info,Position>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
 
     #[account(
         mut,
-        token::mint = token_mint_0
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
     )]
     pub user_token_0 : InterfaceAccount<'info,TokenAccount>,
 
     #[account(
         mut,
-        token::mint = token_mint_1
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
     )]
     pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
 
     #[account(
         mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault,
         token::mint = token_mint_0
     )]
     pub pool_token_0 : InterfaceAccount<'info,TokenAccount>,
 
     #[account(
         mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault,
         token::mint = token_mint_1
     )]
     pub pool_token_1 : InterfaceAccount<'info,TokenAccount>,
-
+    #[account(
+        mint::token_program = token_program_0
+    )]
     pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
     pub token_mint_1 : InterfaceAccount<'info,Mint>,
     pub system_program : Program<'info,System>,
-    pub token_program : Interface<'info,TokenInterface>
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>
 }
 
 impl <'info> IncreaseLiquidity<'info>{
@@ -65,6 +102,8 @@ impl <'info> IncreaseLiquidity<'info>{
     )->Result<(u64,u64)>{
         let pool = &mut self.pool;
         let position = &mut self.position;
+        require!(!pool.is_paused(POOL_STATUS_DEPOSIT_PAUSED), ErrorCode::OperationPaused);
+        pool.lock()?;
 
         require!(
             lower_tick < uppar_tick
@@ -75,21 +114,12 @@ impl <'info> IncreaseLiquidity<'info>{
         require!(liquidity_amount > 0 , ErrorCode::InsufficentAmount);
 
         require!(
-            pool.current_tick >= lower_tick && pool.current_tick < uppar_tick,
-            ErrorCode::MintRangeMustCoverCurrentPrice
+            position.tick_lower == lower_tick && position.tick_uppar == uppar_tick,
+            ErrorCode::InvalidPositionRange
         );
-        let lower_tick_array = &mut self.lower_tick_array;
-        let uppar_tick_array = &mut self.uppar_tick_array;
-        
-        let lower_tick_info = lower_tick_array
-             .get_tick_info_mutable(lower_tick, pool.tick_spacing)?;
+        let (mut lower_tick_array, mut uppar_tick_array) =
+            TickArray::load_range_mut(&self.lower_tick_array, &self.uppar_tick_array)?;
         
-        let uppar_tick_info = uppar_tick_array
-            .get_tick_info_mutable(uppar_tick, pool.tick_spacing)?;
-
-        lower_tick_info.update_liquidity(liquidity_amount as i128,true)?;
-
-        lower_tick_info.update_liquidity(liquidity_amount as i128,false)?;
         let (amount_0, amount_1) = get_amount_for_liquidity(
             pool.sqrt_price_x96,
             get_sqrt_price_from_tick(lower_tick)?,
@@ -97,37 +127,47 @@ impl <'info> IncreaseLiquidity<'info>{
             liquidity_amount,
         )?;   
         
-        pool.global_liquidity = pool
-            .global_liquidity
-            .checked_add(liquidity_amount)
+        let block_timestamp = Clock::get()?.unix_timestamp as u32;
+        let cumulatives = ObservationState::record(
+            &self.observation_state,
+            block_timestamp,
+            pool.current_tick,
+            pool.global_liquidity
+        )?;
+        modify_position(
+            pool,
+            position,
+            &mut lower_tick_array,
+            uppar_tick_array.as_deref_mut(),
+            liquidity_amount as i128,
+            Some(&cumulatives)
+        )?;
+
+        //the vaults have to receive the full amounts, so the user also covers any transfer fee
+        let amount_0 = amount_0
+            .checked_add(get_transfer_inverse_fee(&self.token_mint_0, amount_0)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let amount_1 = amount_1
+            .checked_add(get_transfer_inverse_fee(&self.token_mint_1, amount_1)?)
             .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-        if amount_0 > 0 {
-            token::transfer(
-                CpiContext::new(
-                    self.token_program.to_account_info(),
-                    Transfer{
-                        from : self.user_token_0.to_account_info(),
-                        to : self.pool_token_0.to_account_info(),
-                        authority : self.payer.to_account_info()
-                    }
-                ),
-                amount_0
-            )?;
-        }
-        if amount_1 > 0{
-            token::transfer(
-                CpiContext::new(
-                    self.token_program.to_account_info(),
-                    Transfer{
-                        from:self.user_token_1.to_account_info(),
-                        to : self.pool_token_1.to_account_info(),
-                        authority : self.payer.to_account_info()
-                    }
-                ),
-                amount_1
-            )?;
-        } 
+        transfer_from_user_to_vault(
+            &self.payer,
+            &self.user_token_0,
+            &self.pool_token_0,
+            &self.token_mint_0,
+            &self.token_program_0,
+            amount_0
+        )?;
+        transfer_from_user_to_vault(
+            &self.payer,
+            &self.user_token_1,
+            &self.pool_token_1,
+            &self.token_mint_1,
+            &self.token_program_1,
+            amount_1
+        )?;
+        self.pool.unlock();
         Ok((amount_0,amount_1))
     }
 }
\ No newline at end of file
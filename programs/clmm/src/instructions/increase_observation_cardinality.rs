@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+use crate::states::{ObservationState, Pool};
+
+#[derive(Accounts)]
+#[instruction(observation_cardinality_next : u32)]
+pub struct IncreaseObservationCardinality<'info>{
+    //anyone who wants a longer twap window pays the rent for it
+    #[account(mut)]
+    pub payer : Signer<'info>,
+    pub pool : Account<'info, Pool>,
+    //an account can only grow by 10KiB per instruction, larger windows take several calls
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump,
+        realloc = ObservationState::space(observation_cardinality_next as usize),
+        realloc::payer = payer,
+        realloc::zero = true
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    pub system_program : Program<'info, System>,
+}
+
+impl <'info> IncreaseObservationCardinality<'info>{
+    pub fn new(&mut self, observation_cardinality_next : u32)->Result<()>{
+        self.observation_state.load_mut()?.increase_cardinality(observation_cardinality_next)
+    }
+}
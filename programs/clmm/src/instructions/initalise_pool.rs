@@ -1,7 +1,9 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
-use crate::{states::Pool, utils::get_tick_at_sqrt_price};
+use crate::utils::ErrorCode;
+use crate::{states::{ObservationState, Pool, OBSERVATION_NUM, DEFAULT_FEE_RATE}, utils::{get_tick_at_sqrt_price, is_supported_mint, tick_spacing_to_max_liquidity_per_tick}};
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(tick_spacing:i32)]
 pub struct InitializePool<'info>{
@@ -18,43 +20,98 @@ pub struct InitializePool<'info>{
             token_mint_1.key().as_ref(),
             tick_spacing.to_le_bytes().as_ref(),
         ],
-        bump
+        bump,
+        //checked with the pool, a duplicate mint would otherwise fail creating the second vault first
+        constraint = token_mint_0.key() != token_mint_1.key() @ErrorCode::DuplicateMint,
+        constraint = token_mint_0.key() < token_mint_1.key() @ErrorCode::InvalidMintOrder
     )]
     pub pool: Account<'info, Pool>,
 
+    #[account(
+        mint::token_program = token_program_0
+    )]
     pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
     pub token_mint_1 : InterfaceAccount<'info,Mint>,
     #[account(
         init,
         payer = payer,
+        seeds = [
+            b"vault",
+            pool.key().as_ref(),
+            token_mint_0.key().as_ref()
+        ],
+        bump,
         token::mint = token_mint_0,
-        token::authority = pool
+        token::authority = pool,
+        token::token_program = token_program_0
     )]
     pub token_vault_0 : InterfaceAccount<'info,TokenAccount>,
     #[account(
         init,
         payer = payer,
+        seeds = [
+            b"vault",
+            pool.key().as_ref(),
+            token_mint_1.key().as_ref()
+        ],
+        bump,
         token::mint = token_mint_1,
-        token::authority = pool
+        token::authority = pool,
+        token::token_program = token_program_1
     )]
     pub token_vault_1 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        space = ObservationState::space(OBSERVATION_NUM),
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
     pub system_program : Program<'info,System>,
     pub associated_token_program : Program<'info,AssociatedToken>,
-    pub token_program : Interface<'info,TokenInterface>
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>
 }
 impl <'info> InitializePool<'info>{
-    pub fn new(&mut self,tick_spacing:i32,inital_sqrt_price:u128,bump:u8)->Result<()>{
+    pub fn new(&mut self,tick_spacing:i32,inital_sqrt_price:u128,bump:u8,observation_bump:u8)->Result<()>{
         
+        require!(inital_sqrt_price > 0, ErrorCode::InvalidSqrtPrice);
+        require!(
+            is_supported_mint(&self.token_mint_0)? && is_supported_mint(&self.token_mint_1)?,
+            ErrorCode::UnsupportedMintExtension
+        );
         let pool = &mut self.pool;
         pool.token_mint_0 = self.token_mint_0.key();
         pool.token_mint_1 = self.token_mint_1.key();
         pool.token_vault_0 = self.token_vault_0.key();
         pool.token_vault_1 = self.token_vault_1.key();
         pool.global_liquidity = 0;
+        pool.limit_order_liquidity = 0;
         pool.sqrt_price_x96 = inital_sqrt_price;
         pool.current_tick = get_tick_at_sqrt_price(inital_sqrt_price)?;
         pool.tick_spacing = tick_spacing;
+        pool.tick_spacing_seed = tick_spacing.to_le_bytes();
+        pool.max_liquidity_per_tick = tick_spacing_to_max_liquidity_per_tick(tick_spacing)?;
         pool.bump = bump;
+        pool.fee_rate = DEFAULT_FEE_RATE;
+        pool.status = 0;
+        pool.unlocked = true;
+
+        let pool_key = pool.key();
+        let (mut observation_state, mut observations) = ObservationState::load_ring_init(&self.observation_state)?;
+        observation_state.initialize(
+            &mut observations,
+            pool_key,
+            observation_bump,
+            Clock::get()?.unix_timestamp as u32
+        );
         Ok(())
     }
 }
\ No newline at end of file
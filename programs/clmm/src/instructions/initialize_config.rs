@@ -0,0 +1,45 @@
+use anchor_lang::prelude::*;
+use crate::program::Clmm;
+use crate::states::{
+    GlobalConfig, DEFAULT_KEEPER_BOUNTY_BPS, DEFAULT_MAX_FEE_RATE, DEFAULT_MAX_REFERRAL_FEE_BPS,
+    DEFAULT_MIN_FEE_RATE
+};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info>{
+    //only whoever can upgrade the program gets to pick the first admin
+    #[account(mut)]
+    pub payer : Signer<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + GlobalConfig::INIT_SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key()) @ErrorCode::Unauthorized
+    )]
+    pub program : Program<'info, Clmm>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(payer.key()) @ErrorCode::Unauthorized
+    )]
+    pub program_data : Account<'info, ProgramData>,
+    pub system_program : Program<'info, System>,
+}
+
+impl <'info> InitializeConfig<'info>{
+    pub fn new(&mut self, admin : Pubkey, bump : u8)->Result<()>{
+        let config = &mut self.config;
+        config.admin = admin;
+        config.pending_admin = Pubkey::default();
+        config.bump = bump;
+        config.min_fee_rate = DEFAULT_MIN_FEE_RATE;
+        config.max_fee_rate = DEFAULT_MAX_FEE_RATE;
+        config.max_referral_fee_bps = DEFAULT_MAX_REFERRAL_FEE_BPS;
+        config.keeper_bounty_bps = DEFAULT_KEEPER_BOUNTY_BPS;
+        Ok(())
+    }
+}
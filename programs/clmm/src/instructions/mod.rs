@@ -10,4 +10,49 @@ pub use decrease_liquidity::*;
 pub mod close_position;
 pub use close_position::*;
 pub mod swap;
-pub use swap::*;
\ No newline at end of file
+pub use swap::*;
+pub mod close_tick_array;
+pub use close_tick_array::*;
+pub mod collect_fees;
+pub use collect_fees::*;
+pub mod initialize_config;
+pub use initialize_config::*;
+pub mod propose_admin;
+pub use propose_admin::*;
+pub mod accept_admin;
+pub use accept_admin::*;
+pub mod set_pool_status;
+pub use set_pool_status::*;
+
+pub mod observe;
+pub use observe::*;
+pub mod increase_observation_cardinality;
+pub use increase_observation_cardinality::*;
+pub mod snapshot_cumulatives_inside;
+pub use snapshot_cumulatives_inside::*;
+pub mod quote_swap;
+pub use quote_swap::*;
+pub mod swap_route;
+pub use swap_route::*;
+pub mod flash;
+pub use flash::*;
+pub mod set_fee_bounds;
+pub use set_fee_bounds::*;
+pub mod set_dynamic_fee;
+pub use set_dynamic_fee::*;
+pub mod set_max_referral_fee;
+pub use set_max_referral_fee::*;
+pub mod place_limit_order;
+pub use place_limit_order::*;
+pub mod claim_limit_order;
+pub use claim_limit_order::*;
+pub mod set_keeper_bounty;
+pub use set_keeper_bounty::*;
+pub mod set_settle_on_cross;
+pub use set_settle_on_cross::*;
+pub mod settle_crossed_position;
+pub use settle_crossed_position::*;
+pub mod zap_in;
+pub use zap_in::*;
+pub mod zap_out;
+pub use zap_out::*;
@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use crate::states::{ObservationState, ObserveResult, Pool};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct Observe<'info>{
+    pub pool : Account<'info, Pool>,
+    #[account(
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+}
+
+impl <'info> Observe<'info>{
+    //seconds_agos is oldest first, e.g. [3600, 0] for the last hour
+    //the twap and the harmonic mean liquidity cover the window from the first to the last entry
+    pub fn new(&self, seconds_agos : Vec<u32>)->Result<ObserveResult>{
+        require!(
+            seconds_agos.len() >= 2 && seconds_agos[0] > seconds_agos[seconds_agos.len() - 1],
+            ErrorCode::InvalidObservationWindow
+        );
+        let pool = &self.pool;
+        let block_timestamp = Clock::get()?.unix_timestamp as u32;
+        let (observation_state, observations) = ObservationState::load_ring(&self.observation_state)?;
+        let (tick_cumulatives, seconds_per_liquidity_cumulatives_x64) = observation_state.observe(
+            &observations,
+            block_timestamp,
+            &seconds_agos,
+            pool.current_tick,
+            pool.global_liquidity
+        )?;
+
+        let last = seconds_agos.len() - 1;
+        let window = (seconds_agos[0] - seconds_agos[last]) as i64;
+        let tick_cumulative_delta = tick_cumulatives[last] - tick_cumulatives[0];
+        //round towards negative infinity so a negative twap is not biased upwards
+        let twap_tick = tick_cumulative_delta.div_euclid(window) as i32;
+
+        let seconds_per_liquidity_delta = seconds_per_liquidity_cumulatives_x64[last]
+            .wrapping_sub(seconds_per_liquidity_cumulatives_x64[0]);
+        let harmonic_mean_liquidity = ((window as u128) << 64)
+            .checked_div(seconds_per_liquidity_delta)
+            .unwrap_or(0);
+
+        Ok(ObserveResult{
+            tick_cumulatives,
+            seconds_per_liquidity_cumulatives_x64,
+            twap_tick,
+            harmonic_mean_liquidity
+        })
+    }
+}
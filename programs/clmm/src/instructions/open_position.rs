@@ -1,9 +1,10 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{ token::{self,  Transfer}, token_interface::{Mint, TokenAccount, TokenInterface}};
-use crate::{states::{Pool, Position, TickArray}, utils::{get_amount_for_liquidity, get_sqrt_price_from_tick}};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{states::{modify_position, ObservationState, Pool, Position, TickArray, POOL_STATUS_DEPOSIT_PAUSED}, utils::{get_amount_for_liquidity, get_sqrt_price_from_tick, get_transfer_inverse_fee, transfer_from_user_to_vault, MAX_TICK, MIN_TICK}};
 use crate::utils::ErrorCode;
 
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(owner:Pubkey,lower_tick:i32,uppar_tick:i32,liquidity_amount:u128,tick_array_lower_start_index:i32,tick_array_uppar_start_index:i32)]
 pub struct OpenPosition<'info>{
@@ -18,7 +19,9 @@ pub struct OpenPosition<'info>{
             pool.token_mint_1.as_ref(),
             pool.tick_spacing.to_le_bytes().as_ref(),
         ],
-        bump = pool.bump
+        bump = pool.bump,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
     )]
     pub pool: Account<'info, Pool>,  
 
@@ -33,7 +36,7 @@ pub struct OpenPosition<'info>{
         ],
         bump
     )]
-    pub lower_tick_array : Account<'info,TickArray>,
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
 
     #[account(
         init_if_needed,
@@ -46,12 +49,12 @@ pub struct OpenPosition<'info>{
         ],
         bump
     )]
-    pub uppar_tick_array : Account<'info,TickArray>,
+    pub uppar_tick_array : AccountLoader<'info, TickArray>,
 
     #[account(
         init_if_needed,
         payer = payer,
-        space = Position::INIT_SPACE,
+        space = 8 + Position::INIT_SPACE,
         seeds = [
             b"position",
             owner.as_ref(),
@@ -62,24 +65,54 @@ pub struct OpenPosition<'info>{
         bump
     )]
     pub position : Account<'info, Position>,
-    
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
     pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
     pub token_mint_1 : InterfaceAccount<'info,Mint>,
 
-    #[account(mut)]
-    pub user_token_0: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
+    )]
+    pub user_token_0 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
+    )]
     pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault
+    )]
     pub pool_token_0 : InterfaceAccount<'info,TokenAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault
+    )]
     pub pool_token_1 : InterfaceAccount<'info,TokenAccount>,
     pub system_program : Program<'info,System>,
-    pub token_program : Interface<'info,TokenInterface>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>,
 
 }
 
 impl <'info>OpenPosition<'info>{
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         &mut self,
         owner: Pubkey,
@@ -91,14 +124,6 @@ impl <'info>OpenPosition<'info>{
         bump:u8   
     )->Result<(u64,u64)>{
         
-        require!(
-            self.user_token_0.mint == self.token_mint_0.key(),
-            ErrorCode::InvalidMint
-        );
-        require!(
-            self.user_token_1.mint == self.token_mint_1.key(),
-            ErrorCode::InvalidMint
-        );
         require!(
             self.user_token_0.owner == owner,
             ErrorCode::Unauthorized
@@ -108,9 +133,13 @@ impl <'info>OpenPosition<'info>{
             ErrorCode::Unauthorized
         );
         let pool = &mut self.pool;
+        require!(!pool.is_paused(POOL_STATUS_DEPOSIT_PAUSED), ErrorCode::OperationPaused);
+        pool.lock()?;
 
         require!(
             lower_tick < uppar_tick
+                && lower_tick >= MIN_TICK
+                && uppar_tick <= MAX_TICK
                 && lower_tick % pool.tick_spacing == 0
                 && uppar_tick % pool.tick_spacing == 0,
             ErrorCode::InvalidTickRange
@@ -119,28 +148,24 @@ impl <'info>OpenPosition<'info>{
 
         require!(liquidity_amount > 0 ,ErrorCode::InsufficentAmount);
 
-        let lower_tick_array = &mut self.lower_tick_array;
-        let uppar_tick_array = &mut self.uppar_tick_array;
+        let (mut lower_tick_array, mut uppar_tick_array) =
+            TickArray::load_range_mut(&self.lower_tick_array, &self.uppar_tick_array)?;
 
         if lower_tick_array.starting_tick == 0 && lower_tick_array.pool ==Pubkey::default(){
             lower_tick_array.pool = pool.key();
             lower_tick_array.starting_tick = _tick_array_lower_start_index;
+            lower_tick_array.funder = self.payer.key();
         }
 
-        if uppar_tick_array.starting_tick == 0  && uppar_tick_array.pool == Pubkey::default() {
-            uppar_tick_array.pool = pool.key();
-            uppar_tick_array.starting_tick = _tick_array_uppar_start_index;
+        //None when both ticks sit in the lower array, which is already set up above
+        if let Some(uppar_tick_array) = uppar_tick_array.as_deref_mut() {
+            if uppar_tick_array.starting_tick == 0  && uppar_tick_array.pool == Pubkey::default() {
+                uppar_tick_array.pool = pool.key();
+                uppar_tick_array.starting_tick = _tick_array_uppar_start_index;
+                uppar_tick_array.funder = self.payer.key();
+            }
         }
 
-        let lower_tick_info = lower_tick_array
-            .get_tick_info_mutable(lower_tick, pool.tick_spacing)?;
-
-        let uppar_tick_info  = uppar_tick_array
-            .get_tick_info_mutable(uppar_tick, pool.tick_spacing)?;
-
-        lower_tick_info.update_liquidity(liquidity_amount as i128, true)?;
-        uppar_tick_info.update_liquidity(liquidity_amount as i128, false)?;
-
         let (amount_0,ampunt_1) = get_amount_for_liquidity(
             pool.sqrt_price_x96,
             get_sqrt_price_from_tick(lower_tick)?,
@@ -153,7 +178,6 @@ impl <'info>OpenPosition<'info>{
             position.pool = pool.key();
             position.tick_uppar = uppar_tick;
             position.tick_lower = lower_tick;
-            position.liquidity = liquidity_amount;
             position.bump = bump;
         }else{
             require!(position.owner == owner , ErrorCode::Unauthorized);
@@ -161,44 +185,49 @@ impl <'info>OpenPosition<'info>{
                 position.tick_lower == lower_tick && position.tick_uppar == uppar_tick,
                 ErrorCode::InvalidPositionRange
             );
-            position.liquidity = position
-                .liquidity
-                .checked_add(liquidity_amount)
-                .ok_or(ErrorCode::ArithmeticOverflow)?;
         }
 
-        //TODO: liq should  added global liq only when current price inside the range
-        pool.global_liquidity = pool
-            .global_liquidity
-            .checked_add(liquidity_amount)
+        let block_timestamp = Clock::get()?.unix_timestamp as u32;
+        let cumulatives = ObservationState::record(
+            &self.observation_state,
+            block_timestamp,
+            pool.current_tick,
+            pool.global_liquidity
+        )?;
+        modify_position(
+            pool,
+            position,
+            &mut lower_tick_array,
+            uppar_tick_array.as_deref_mut(),
+            liquidity_amount as i128,
+            Some(&cumulatives)
+        )?;
+
+        //the vaults have to receive the full amounts, so the user also covers any transfer fee
+        let amount_0 = amount_0
+            .checked_add(get_transfer_inverse_fee(&self.token_mint_0, amount_0)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let ampunt_1 = ampunt_1
+            .checked_add(get_transfer_inverse_fee(&self.token_mint_1, ampunt_1)?)
             .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-        if amount_0 > 0 {
-            token::transfer(
-                CpiContext::new(
-                    self.token_program.to_account_info(),
-                    Transfer{
-                        from:self.user_token_0.to_account_info(),
-                        to:self.pool_token_0.to_account_info(),
-                        authority : self.payer.to_account_info()
-                    },
-                ),
-                amount_0
-            )?;
-        }
-        if ampunt_1 > 0 {
-            token::transfer(
-                CpiContext::new(
-                    self.token_program.to_account_info(),
-                    Transfer{
-                        from : self.user_token_1.to_account_info(),
-                        to : self.pool_token_1.to_account_info(),
-                        authority : self.payer.to_account_info()
-                    }
-                ),
-                ampunt_1
-            )?;
-        }
+        transfer_from_user_to_vault(
+            &self.payer,
+            &self.user_token_0,
+            &self.pool_token_0,
+            &self.token_mint_0,
+            &self.token_program_0,
+            amount_0
+        )?;
+        transfer_from_user_to_vault(
+            &self.payer,
+            &self.user_token_1,
+            &self.pool_token_1,
+            &self.token_mint_1,
+            &self.token_program_1,
+            ampunt_1
+        )?;
+        self.pool.unlock();
         Ok((amount_0,ampunt_1))
     }
 }
\ No newline at end of file
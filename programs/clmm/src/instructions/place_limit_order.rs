@@ -0,0 +1,187 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{
+    states::{modify_limit_order, LimitOrder, ObservationState, Pool, TickArray, POOL_STATUS_DEPOSIT_PAUSED},
+    utils::{
+        get_amount_for_liquidity, get_sqrt_price_from_tick, get_transfer_inverse_fee,
+        transfer_from_user_to_vault, MAX_TICK, MIN_TICK
+    }
+};
+use crate::utils::ErrorCode;
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(tick : i32, sell_token_0 : bool, liquidity_amount : u128, tick_array_start_index : i32)]
+pub struct PlaceLimitOrder<'info>{
+    #[account(mut)]
+    pub payer : Signer<'info>,
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
+    pub pool : Account<'info,Pool>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = TickArray::SPACE,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &tick_array_start_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + LimitOrder::INIT_SPACE,
+        seeds = [
+            b"limit_order",
+            payer.key().as_ref(),
+            pool.key().as_ref(),
+            &tick.to_le_bytes()
+        ],
+        bump
+    )]
+    pub limit_order : Account<'info, LimitOrder>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
+    )]
+    pub user_token_0 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
+    )]
+    pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_0 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_1 : InterfaceAccount<'info,TokenAccount>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
+    pub system_program : Program<'info,System>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>,
+}
+
+impl <'info> PlaceLimitOrder<'info>{
+    //the order covers [tick, tick + tick_spacing] and has to sit entirely on the side of the price
+    //that only holds the token being sold, placing again on the same tick adds to the order
+    //until it is filled
+    pub fn new(
+        &mut self,
+        tick : i32,
+        sell_token_0 : bool,
+        liquidity_amount : u128,
+        tick_array_start_index : i32,
+        bump : u8
+    )->Result<u64>{
+        let pool = &mut self.pool;
+        require!(!pool.is_paused(POOL_STATUS_DEPOSIT_PAUSED), ErrorCode::OperationPaused);
+        pool.lock()?;
+        require!(liquidity_amount > 0, ErrorCode::InsufficentAmount);
+
+        let tick_upper = tick + pool.tick_spacing;
+        require!(
+            tick % pool.tick_spacing == 0
+                && tick >= MIN_TICK
+                && tick_upper <= MAX_TICK
+                && TickArray::get_starting_tick_index(tick, pool.tick_spacing) == tick_array_start_index
+                && TickArray::get_starting_tick_index(tick_upper, pool.tick_spacing) == tick_array_start_index,
+            ErrorCode::InvalidLimitOrder
+        );
+        if sell_token_0 {
+            require!(pool.current_tick < tick, ErrorCode::InvalidLimitOrder);
+        } else {
+            require!(tick_upper <= pool.current_tick, ErrorCode::InvalidLimitOrder);
+        }
+
+        let mut tick_array = TickArray::load_or_init(&self.tick_array)?;
+        if tick_array.starting_tick == 0 && tick_array.pool == Pubkey::default() {
+            tick_array.pool = pool.key();
+            tick_array.starting_tick = tick_array_start_index;
+            tick_array.funder = self.payer.key();
+        }
+
+        let order = &mut self.limit_order;
+        let fill_tick = if sell_token_0 { tick_upper } else { tick };
+        let fill_tick_info = tick_array.get_tick_info_mutable(fill_tick, pool.tick_spacing)?;
+        if order.owner == Pubkey::default() {
+            order.owner = self.payer.key();
+            order.pool = pool.key();
+            order.tick = tick;
+            order.sell_token_0 = sell_token_0;
+            order.fill_count = if sell_token_0 {
+                fill_tick_info.limit_order_fill_count_0
+            } else {
+                fill_tick_info.limit_order_fill_count_1
+            };
+            order.bump = bump;
+        } else {
+            require!(order.sell_token_0 == sell_token_0, ErrorCode::InvalidLimitOrder);
+            require!(!order.is_filled(fill_tick_info), ErrorCode::LimitOrderFilled);
+        }
+
+        let (amount_0, amount_1) = get_amount_for_liquidity(
+            pool.sqrt_price_x96,
+            get_sqrt_price_from_tick(tick)?,
+            get_sqrt_price_from_tick(tick_upper)?,
+            liquidity_amount
+        )?;
+        let block_timestamp = Clock::get()?.unix_timestamp as u32;
+        let cumulatives = ObservationState::record(
+            &self.observation_state,
+            block_timestamp,
+            pool.current_tick,
+            pool.global_liquidity
+        )?;
+        modify_limit_order(pool, order, &mut tick_array, liquidity_amount as i128, Some(&cumulatives))?;
+
+        //the order is out of range, so only the sold token is deposited
+        let (amount, user_token, pool_token, mint, token_program) = if sell_token_0 {
+            (amount_0, &self.user_token_0, &self.pool_token_0, &self.token_mint_0, &self.token_program_0)
+        } else {
+            (amount_1, &self.user_token_1, &self.pool_token_1, &self.token_mint_1, &self.token_program_1)
+        };
+        let amount = amount
+            .checked_add(get_transfer_inverse_fee(mint, amount)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        transfer_from_user_to_vault(
+            &self.payer,
+            user_token,
+            pool_token,
+            mint,
+            token_program,
+            amount
+        )?;
+
+        self.pool.unlock();
+        Ok(amount)
+    }
+}
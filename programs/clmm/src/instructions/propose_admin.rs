@@ -0,0 +1,24 @@
+use anchor_lang::prelude::*;
+use crate::states::GlobalConfig;
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info>{
+    pub admin : Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin @ErrorCode::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+}
+
+impl <'info> ProposeAdmin<'info>{
+    //the current admin stays in charge until the new one accepts,
+    //proposing Pubkey::default() cancels a pending transfer
+    pub fn new(&mut self, new_admin : Pubkey)->Result<()>{
+        self.config.pending_admin = new_admin;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+use crate::{
+    instructions::swap_internal,
+    states::{GlobalConfig, Observation, Pool, TickArray},
+    utils::get_transfer_fee
+};
+use crate::utils::ErrorCode;
+
+//same accounts as a swap minus everything that moves tokens, none of them is writable
+#[derive(Accounts)]
+pub struct QuoteSwap<'info>{
+    #[account(
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
+    pub pool : Account<'info,Pool>,
+    #[account(
+        constraint = tick_array.key() == Pubkey::find_program_address(
+            &[
+                b"tick_array".as_ref(),
+                pool.key().as_ref(),
+                &TickArray::get_starting_tick_index(pool.current_tick, pool.tick_spacing).to_le_bytes()
+            ],
+            &crate::ID
+        ).0 @ ErrorCode::InvalidTickArrayAccount
+    )]
+    pub tick_array: AccountLoader<'info, TickArray>,
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
+    //only needed when the pool uses the dynamic fee, the config holds its bounds
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Option<Account<'info, GlobalConfig>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SwapQuote{
+    pub amount_in : u64,
+    pub amount_out : u64, //what would land in the user account, after transfer fees
+    pub fee_amount : u64,
+    pub sqrt_price_x96 : u128,
+    pub tick : i32,
+    pub ticks_crossed : u32
+}
+
+impl <'info> QuoteSwap<'info>{
+    //meant for simulateTransaction, the result comes back as return data
+    pub fn new(
+        &mut self,
+        amount_in : u64,
+        swap_token_0_for_1 : bool,
+        remaining_accounts : &'info [AccountInfo<'info>]
+    )->Result<SwapQuote>{
+        require!(amount_in >0,ErrorCode::InsufficentAmount);
+        let (mint_in, mint_out) = if swap_token_0_for_1 {
+            (&self.token_mint_0, &self.token_mint_1)
+        } else {
+            (&self.token_mint_1, &self.token_mint_0)
+        };
+        let amount_in_received = amount_in
+            .checked_sub(get_transfer_fee(mint_in, amount_in)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        //pool and tick arrays are read only, the swap only changes copies of them
+        //and the oracle values crossed ticks would pick up are never written either
+        let swap_result = swap_internal(
+            &mut self.pool,
+            &mut self.tick_array.load()?.boxed_copy(),
+            remaining_accounts,
+            amount_in_received,
+            swap_token_0_for_1,
+            &Observation::default(),
+            self.config.as_deref(),
+            false
+        )?;
+        let amount_out = swap_result.amount_out
+            .checked_sub(get_transfer_fee(mint_out, swap_result.amount_out)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(SwapQuote{
+            amount_in,
+            amount_out,
+            fee_amount : swap_result.fee_amount,
+            sqrt_price_x96 : self.pool.sqrt_price_x96,
+            tick : self.pool.current_tick,
+            ticks_crossed : swap_result.ticks_crossed
+        })
+    }
+}
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+use crate::states::{GlobalConfig, Pool, DEFAULT_FEE_RATE};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct SetDynamicFee<'info>{
+    pub admin : Signer<'info>,
+    #[account(
+        has_one = admin @ErrorCode::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+    #[account(mut)]
+    pub pool : Account<'info, Pool>,
+}
+
+impl <'info> SetDynamicFee<'info>{
+    //turning it on starts from a calm pool at the config minimum, turning it off goes back to the fixed fee
+    pub fn new(&mut self, enabled : bool)->Result<()>{
+        let pool = &mut self.pool;
+        pool.dynamic_fee = enabled;
+        pool.volatility_accumulator = 0;
+        if enabled {
+            pool.fee_rate = self.config.min_fee_rate;
+        } else {
+            pool.fee_rate = DEFAULT_FEE_RATE;
+        }
+        Ok(())
+    }
+}
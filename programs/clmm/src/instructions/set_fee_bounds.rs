@@ -0,0 +1,28 @@
+use anchor_lang::prelude::*;
+use crate::states::GlobalConfig;
+use crate::utils::{ErrorCode, FEE_RATE_DENOMINATOR};
+
+#[derive(Accounts)]
+pub struct SetFeeBounds<'info>{
+    pub admin : Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin @ErrorCode::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+}
+
+impl <'info> SetFeeBounds<'info>{
+    //dynamic fee pools read the bounds on every swap, so they apply from the next swap on
+    pub fn new(&mut self, min_fee_rate : u32, max_fee_rate : u32)->Result<()>{
+        require!(
+            min_fee_rate <= max_fee_rate && max_fee_rate < FEE_RATE_DENOMINATOR,
+            ErrorCode::InvalidFeeRate
+        );
+        self.config.min_fee_rate = min_fee_rate;
+        self.config.max_fee_rate = max_fee_rate;
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use anchor_lang::prelude::*;
+use crate::states::{GlobalConfig, MAX_KEEPER_BOUNTY_BPS};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct SetKeeperBounty<'info>{
+    pub admin : Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin @ErrorCode::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+}
+
+impl <'info> SetKeeperBounty<'info>{
+    pub fn new(&mut self, keeper_bounty_bps : u16)->Result<()>{
+        require!(keeper_bounty_bps <= MAX_KEEPER_BOUNTY_BPS, ErrorCode::InvalidKeeperBounty);
+        self.config.keeper_bounty_bps = keeper_bounty_bps;
+        Ok(())
+    }
+}
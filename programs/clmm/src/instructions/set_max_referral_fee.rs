@@ -0,0 +1,23 @@
+use anchor_lang::prelude::*;
+use crate::states::{GlobalConfig, MAX_REFERRAL_FEE_BPS};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct SetMaxReferralFee<'info>{
+    pub admin : Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin @ErrorCode::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+}
+
+impl <'info> SetMaxReferralFee<'info>{
+    pub fn new(&mut self, max_referral_fee_bps : u16)->Result<()>{
+        require!(max_referral_fee_bps <= MAX_REFERRAL_FEE_BPS, ErrorCode::ReferralFeeTooHigh);
+        self.config.max_referral_fee_bps = max_referral_fee_bps;
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+use crate::states::{
+    GlobalConfig, Pool, POOL_STATUS_COLLECT_FEE_PAUSED, POOL_STATUS_DEPOSIT_PAUSED,
+    POOL_STATUS_FLASH_PAUSED, POOL_STATUS_SWAP_PAUSED, POOL_STATUS_WITHDRAW_PAUSED
+};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct SetPoolStatus<'info>{
+    pub admin : Signer<'info>,
+    #[account(
+        has_one = admin @ErrorCode::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+    #[account(mut)]
+    pub pool : Account<'info, Pool>,
+}
+
+impl <'info> SetPoolStatus<'info>{
+    //status replaces the whole bit set, 0 resumes every operation
+    pub fn new(&mut self, status : u8)->Result<()>{
+        let known = POOL_STATUS_SWAP_PAUSED
+            | POOL_STATUS_DEPOSIT_PAUSED
+            | POOL_STATUS_WITHDRAW_PAUSED
+            | POOL_STATUS_COLLECT_FEE_PAUSED
+            | POOL_STATUS_FLASH_PAUSED;
+        require!(status & !known == 0, ErrorCode::InvalidStatus);
+        self.pool.status = status;
+        Ok(())
+    }
+}
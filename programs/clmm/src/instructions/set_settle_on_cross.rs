@@ -0,0 +1,25 @@
+use anchor_lang::prelude::*;
+use crate::states::{Position, POSITION_SETTLE_ABOVE, POSITION_SETTLE_BELOW, POSITION_SETTLE_NONE};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+pub struct SetSettleOnCross<'info>{
+    pub owner : Signer<'info>,
+    #[account(
+        mut,
+        has_one = owner @ErrorCode::Unauthorized
+    )]
+    pub position : Account<'info, Position>,
+}
+
+impl <'info> SetSettleOnCross<'info>{
+    //POSITION_SETTLE_NONE opts the position back out
+    pub fn new(&mut self, settle_on_cross : u8)->Result<()>{
+        require!(
+            matches!(settle_on_cross, POSITION_SETTLE_NONE | POSITION_SETTLE_ABOVE | POSITION_SETTLE_BELOW),
+            ErrorCode::InvalidSettleMode
+        );
+        self.position.settle_on_cross = settle_on_cross;
+        Ok(())
+    }
+}
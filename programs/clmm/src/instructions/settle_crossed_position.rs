@@ -0,0 +1,194 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
+use crate::{
+    states::{
+        modify_position, GlobalConfig, ObservationState, Pool, Position, TickArray, POOL_STATUS_WITHDRAW_PAUSED,
+        POSITION_SETTLE_ABOVE, POSITION_SETTLE_BELOW
+    },
+    utils::{get_amount_for_liquidity, get_sqrt_price_from_tick, transfer_from_vault_to_user}
+};
+use crate::utils::ErrorCode;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SettleCrossedPosition<'info>{
+    //anyone can crank this, the keeper only ever receives the bounty
+    #[account(mut)]
+    pub keeper : Signer<'info>,
+    /// CHECK: only receives the position rent, checked against position.owner
+    #[account(mut)]
+    pub owner : UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Account<'info, GlobalConfig>,
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
+    pub pool : Account<'info, Pool>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_lower, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_uppar, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub upper_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ErrorCode::Unauthorized,
+        has_one = pool @ErrorCode::InvalidPositionRange
+    )]
+    pub position : Account<'info, Position>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    //always the owner's associated token account, created by the keeper if the owner closed it,
+    //so the owner cannot keep the position from settling by closing or handing off an account
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        associated_token::mint = token_mint_0,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program_0
+    )]
+    pub owner_token_0 : Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        associated_token::mint = token_mint_1,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program_1
+    )]
+    pub owner_token_1 : Box<InterfaceAccount<'info, TokenAccount>>,
+    //in the token the position is withdrawn in
+    #[account(mut)]
+    pub keeper_token : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_0 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_1 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
+    pub system_program : Program<'info,System>,
+    pub associated_token_program : Program<'info,AssociatedToken>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>,
+}
+
+impl <'info> SettleCrossedPosition<'info>{
+    //withdraws an opted in position once the price is entirely past it on the side the owner picked,
+    //the keeper bounty comes out of the withdrawn liquidity, uncollected fees go to the owner in full
+    pub fn new(&mut self)->Result<(u128,u64,u64,u64)>{
+        let pool = &mut self.pool;
+        let position = &mut self.position;
+        require!(!pool.is_paused(POOL_STATUS_WITHDRAW_PAUSED), ErrorCode::OperationPaused);
+        pool.lock()?;
+
+        let crossed = match position.settle_on_cross {
+            POSITION_SETTLE_ABOVE => pool.current_tick >= position.tick_uppar,
+            POSITION_SETTLE_BELOW => pool.current_tick < position.tick_lower,
+            _ => false
+        };
+        require!(crossed, ErrorCode::PositionNotCrossed);
+        let liquidity = position.liquidity;
+        require!(liquidity > 0, ErrorCode::NoLiquidityToRemove);
+
+        let (amount_0, amount_1) = get_amount_for_liquidity(
+            pool.sqrt_price_x96,
+            get_sqrt_price_from_tick(position.tick_lower)?,
+            get_sqrt_price_from_tick(position.tick_uppar)?,
+            liquidity
+        )?;
+        //written before the liquidity changes, so the last interval is weighted with the old value
+        ObservationState::record(&self.observation_state, Clock::get()?.unix_timestamp as u32, pool.current_tick, pool.global_liquidity)?;
+        let (mut lower_tick_array, mut upper_tick_array) =
+            TickArray::load_range_mut(&self.lower_tick_array, &self.upper_tick_array)?;
+        modify_position(
+            pool,
+            position,
+            &mut lower_tick_array,
+            upper_tick_array.as_deref_mut(),
+            -(liquidity as i128),
+            None
+        )?;
+
+        //the position is out of range, so only one of the amounts is non zero
+        let withdrawn = amount_0.max(amount_1);
+        let keeper_bounty = (withdrawn as u128 * self.config.keeper_bounty_bps as u128 / 10_000) as u64;
+        let (amount_0, amount_1, pool_token_out, mint_out, token_program_out) = if amount_0 > 0 {
+            (amount_0 - keeper_bounty, amount_1, &self.pool_token_0, &self.token_mint_0, &self.token_program_0)
+        } else {
+            (amount_0, amount_1 - keeper_bounty, &self.pool_token_1, &self.token_mint_1, &self.token_program_1)
+        };
+        require_keys_eq!(self.keeper_token.mint, mint_out.key(), ErrorCode::InvalidMint);
+        let amount_0 = amount_0
+            .checked_add(position.tokens_owed_0)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let amount_1 = amount_1
+            .checked_add(position.tokens_owed_1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_0,
+            &self.owner_token_0,
+            &self.token_mint_0,
+            &self.token_program_0,
+            amount_0
+        )?;
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_1,
+            &self.owner_token_1,
+            &self.token_mint_1,
+            &self.token_program_1,
+            amount_1
+        )?;
+        transfer_from_vault_to_user(
+            &self.pool,
+            pool_token_out,
+            &self.keeper_token,
+            mint_out,
+            token_program_out,
+            keeper_bounty
+        )?;
+
+        self.pool.unlock();
+        Ok((liquidity, amount_0, amount_1, keeper_bounty))
+    }
+}
@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+use crate::states::{get_cumulatives_inside, ObservationState, Pool, TickArray};
+use crate::utils::ErrorCode;
+
+#[derive(Accounts)]
+#[instruction(tick_lower : i32, tick_upper : i32)]
+pub struct SnapshotCumulativesInside<'info>{
+    pub pool : Account<'info, Pool>,
+    #[account(
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    #[account(
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(tick_lower, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(tick_upper, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub uppar_tick_array : AccountLoader<'info, TickArray>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CumulativesInside{
+    pub tick_cumulative_inside : i64,
+    pub seconds_per_liquidity_inside_x64 : u128,
+    pub seconds_inside : u32
+}
+
+impl <'info> SnapshotCumulativesInside<'info>{
+    //read only, meant to be called through CPI by incentive programs
+    //compare two snapshots of the same range to get how long it was in range
+    pub fn new(&mut self, tick_lower : i32, tick_upper : i32)->Result<CumulativesInside>{
+        let pool = &self.pool;
+        require!(tick_lower < tick_upper, ErrorCode::InvalidTickRange);
+        let tick_spacing = pool.tick_spacing;
+        let tick_lower_info = self.lower_tick_array.load()?.get_tick_info(tick_lower, tick_spacing)?;
+        let tick_upper_info = self.uppar_tick_array.load()?.get_tick_info(tick_upper, tick_spacing)?;
+        require!(
+            tick_lower_info.initialized == 1 && tick_upper_info.initialized == 1,
+            ErrorCode::InvalidTickRange
+        );
+
+        let (observation_state, observations) = ObservationState::load_ring(&self.observation_state)?;
+        let cumulatives = observation_state.snapshot(
+            &observations,
+            Clock::get()?.unix_timestamp as u32,
+            pool.current_tick,
+            pool.global_liquidity
+        );
+        let (tick_cumulative_inside, seconds_per_liquidity_inside_x64, seconds_inside) = get_cumulatives_inside(
+            &tick_lower_info,
+            &tick_upper_info,
+            tick_lower,
+            tick_upper,
+            pool.current_tick,
+            &cumulatives
+        );
+        Ok(CumulativesInside{
+            tick_cumulative_inside,
+            seconds_per_liquidity_inside_x64,
+            seconds_inside
+        })
+    }
+}
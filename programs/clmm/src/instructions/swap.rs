@@ -1,19 +1,44 @@
 use anchor_lang::prelude::*;
-use anchor_spl::{ token::{self,  Transfer}, token_interface::{ TokenAccount, TokenInterface}};
-use crate::{states::{Pool, TickArray}, utils::{get_tick_at_sqrt_price, swap_segment}};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{
+    states::{GlobalConfig, Observation, ObservationState, Pool, TickArray, POOL_STATUS_SWAP_PAUSED},
+    utils::{
+        get_fee_growth_delta, get_tick_at_sqrt_price, get_transfer_fee, get_transfer_inverse_fee,
+        invoke_callback, swap_segment, FlashSwapCallbackArgs, FLASH_SWAP_CALLBACK_DISCRIMINATOR,
+        transfer_from_user, transfer_from_user_to_vault, transfer_from_vault_to_user
+    }
+};
 use crate::utils::ErrorCode;
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(amount_in : u64,swap_token_0_for_1 : bool,amount_out_minimum :u64)]
 pub struct Swap<'info>{
-    #[account(mut)]
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
     pub pool : Account<'info,Pool>,
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
+    )]
     pub user_token_0 : InterfaceAccount<'info,TokenAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
+    )]
     pub user_token_1 : InterfaceAccount<'info,TokenAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault
+    )]
     pub pool_token_0 : InterfaceAccount<'info,TokenAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault
+    )]
     pub pool_token_1 : InterfaceAccount<'info,TokenAccount>,
     #[account(
         mut,
@@ -26,97 +51,447 @@ pub struct Swap<'info>{
             &crate::ID
         ).0 @ ErrorCode::InvalidTickArrayAccount
     )]
-    pub tick_array: Account<'info, TickArray>,
+    pub tick_array: AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
     pub payer : Signer<'info>,
     pub system_program : Program<'info,System>,
-    pub token_program : Interface<'info,TokenInterface>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>,
+    //only needed when the swap pays a referral fee, the config caps it, or when the pool uses the dynamic fee
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Option<Account<'info, GlobalConfig>>,
+    #[account(mut)]
+    pub referrer_token : Option<InterfaceAccount<'info, TokenAccount>>,
 }
 impl <'info> Swap<'info>{
+    //referral_fee_bps > 0 needs the config and a referrer_token, the referral fee is taken from
+    //the input if referrer_token holds the input mint and from the output if it holds the output mint
     pub fn new(
         &mut self,
         amount_in : u64,
         swap_token_0_for_1 : bool,
-        amount_out_minimum :u64
-    )->Result<(u64)>{
-        let pool = &mut self.pool;
+        amount_out_minimum :u64,
+        referral_fee_bps : u16,
+        remaining_accounts : &'info [AccountInfo<'info>]
+    )->Result<(u64,u64,u64)>{
+        require!(!self.pool.is_paused(POOL_STATUS_SWAP_PAUSED), ErrorCode::OperationPaused);
+        self.pool.lock()?;
         require!(amount_in >0,ErrorCode::InsufficentAmount);
-        require!(pool.global_liquidity >0,ErrorCode::InsufficentAmount);
 
-        
-        let (amount_in_used, amount_out_calculated, new_sqrt_price_x96) = swap_segment(
-            pool.sqrt_price_x96,
-            pool.global_liquidity,
+        let (mint_in, mint_out, token_program_in, token_program_out) = if swap_token_0_for_1 {
+            (&self.token_mint_0, &self.token_mint_1, &self.token_program_0, &self.token_program_1)
+        } else {
+            (&self.token_mint_1, &self.token_mint_0, &self.token_program_1, &self.token_program_0)
+        };
+
+        let referrer_token = if referral_fee_bps > 0 {
+            let config = self.config.as_ref().ok_or(ErrorCode::MissingReferralAccount)?;
+            require!(
+                referral_fee_bps <= config.max_referral_fee_bps,
+                ErrorCode::ReferralFeeTooHigh
+            );
+            let referrer_token = self.referrer_token.as_ref().ok_or(ErrorCode::MissingReferralAccount)?;
+            require!(
+                referrer_token.mint == mint_in.key() || referrer_token.mint == mint_out.key(),
+                ErrorCode::InvalidMint
+            );
+            Some(referrer_token)
+        } else {
+            None
+        };
+        let referral_from_input = referrer_token.is_some_and(|token| token.mint == mint_in.key());
+        let get_referral_fee = |amount : u64| (amount as u128 * referral_fee_bps as u128 / 10_000) as u64;
+
+        let referral_fee_in = if referral_from_input { get_referral_fee(amount_in) } else { 0 };
+        let amount_in_to_pool = amount_in - referral_fee_in;
+        //the vault only receives what the input mint leaves after its transfer fee
+        let amount_in_received = amount_in_to_pool
+            .checked_sub(get_transfer_fee(mint_in, amount_in_to_pool)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        //the oracle records the price that held up to this swap
+        let block_timestamp = Clock::get()?.unix_timestamp as u32;
+        let cumulatives = ObservationState::record(
+            &self.observation_state,
+            block_timestamp,
+            self.pool.current_tick,
+            self.pool.global_liquidity
+        )?;
+
+        let swap_result = swap_internal(
+            &mut self.pool,
+            &mut *self.tick_array.load_mut()?,
+            remaining_accounts,
+            amount_in_received,
+            swap_token_0_for_1,
+            &cumulatives,
+            self.config.as_deref(),
+            true
+        )?;
+
+        let referral_fee_out = if referrer_token.is_some() && !referral_from_input {
+            get_referral_fee(swap_result.amount_out)
+        } else {
+            0
+        };
+        let amount_out_to_user = swap_result.amount_out - referral_fee_out;
+        //same on the way out, slippage is checked against what actually lands in the user account
+        let amount_out_received = amount_out_to_user
+            .checked_sub(get_transfer_fee(mint_out, amount_out_to_user)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            amount_out_received >= amount_out_minimum,
+            ErrorCode::SlippageExceeded
+        );
+
+        let (
+            user_token_in,
+            user_token_out,
+            pool_token_in,
+            pool_token_out
+        ) = if swap_token_0_for_1 {
+            (&self.user_token_0, &self.user_token_1, &self.pool_token_0, &self.pool_token_1)
+        } else {
+            (&self.user_token_1, &self.user_token_0, &self.pool_token_1, &self.pool_token_0)
+        };
+        transfer_from_user_to_vault(
+            &self.payer,
+            user_token_in,
+            pool_token_in,
+            mint_in,
+            token_program_in,
+            amount_in_to_pool
+        )?;
+        transfer_from_vault_to_user(
+            &self.pool,
+            pool_token_out,
+            user_token_out,
+            mint_out,
+            token_program_out,
+            amount_out_to_user
+        )?;
+        if let Some(referrer_token) = referrer_token {
+            if referral_from_input {
+                transfer_from_user(
+                    &self.payer,
+                    user_token_in,
+                    referrer_token,
+                    mint_in,
+                    token_program_in,
+                    referral_fee_in
+                )?;
+            } else {
+                transfer_from_vault_to_user(
+                    &self.pool,
+                    pool_token_out,
+                    referrer_token,
+                    mint_out,
+                    token_program_out,
+                    referral_fee_out
+                )?;
+            }
+        }
+
+        self.pool.unlock();
+        Ok((amount_out_received, swap_result.fee_amount, referral_fee_in + referral_fee_out))
+    }
+}
+
+impl <'info> Swap<'info>{
+    //same swap, but the output is paid first and the input is collected by calling back into
+    //the borrower program, remaining_accounts are the tick arrays (tick_array_count of them)
+    //followed by the borrower program and the accounts its callback needs
+    pub fn flash_swap(
+        &mut self,
+        amount_in : u64,
+        swap_token_0_for_1 : bool,
+        amount_out_minimum : u64,
+        tick_array_count : u8,
+        data : Vec<u8>,
+        remaining_accounts : &'info [AccountInfo<'info>]
+    )->Result<(u64,u64)>{
+        require!(!self.pool.is_paused(POOL_STATUS_SWAP_PAUSED), ErrorCode::OperationPaused);
+        self.pool.lock()?;
+        require!(amount_in >0,ErrorCode::InsufficentAmount);
+        require!(
+            remaining_accounts.len() > tick_array_count as usize,
+            ErrorCode::InvalidCallbackProgram
+        );
+        let (tick_arrays, callback_accounts) = remaining_accounts.split_at(tick_array_count as usize);
+        let (callback_program, callback_accounts) = callback_accounts.split_first().unwrap();
+
+        let (mint_in, mint_out, token_program_out) = if swap_token_0_for_1 {
+            (&self.token_mint_0, &self.token_mint_1, &self.token_program_1)
+        } else {
+            (&self.token_mint_1, &self.token_mint_0, &self.token_program_0)
+        };
+        //amount_in is what the input vault has to end up with, the borrower also covers the transfer fee
+        let amount_owed = amount_in
+            .checked_add(get_transfer_inverse_fee(mint_in, amount_in)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let block_timestamp = Clock::get()?.unix_timestamp as u32;
+        let cumulatives = ObservationState::record(
+            &self.observation_state,
+            block_timestamp,
+            self.pool.current_tick,
+            self.pool.global_liquidity
+        )?;
+        //if the repayment comes up short the whole instruction fails and none of this is kept
+        let swap_result = swap_internal(
+            &mut self.pool,
+            &mut *self.tick_array.load_mut()?,
+            tick_arrays,
             amount_in,
             swap_token_0_for_1,
+            &cumulatives,
+            self.config.as_deref(),
+            true
         )?;
+
+        let amount_out_received = swap_result.amount_out
+            .checked_sub(get_transfer_fee(mint_out, swap_result.amount_out)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
         require!(
-            amount_out_calculated >= amount_out_minimum,
+            amount_out_received >= amount_out_minimum,
             ErrorCode::SlippageExceeded
         );
-        let signer_seeds: &[&[&[u8]]] = &[&[
-            b"pool",
-            pool.token_mint_0.as_ref(),
-            pool.token_mint_1.as_ref(),
-            &pool.tick_spacing.to_le_bytes(),
-            &[pool.bump],
-        ]];
+        let (user_token_out, pool_token_out) = if swap_token_0_for_1 {
+            (&self.user_token_1, &self.pool_token_1)
+        } else {
+            (&self.user_token_0, &self.pool_token_0)
+        };
+        transfer_from_vault_to_user(
+            &self.pool,
+            pool_token_out,
+            user_token_out,
+            mint_out,
+            token_program_out,
+            swap_result.amount_out
+        )?;
+
+        //the pool goes out locked, though a callback calling back into clmm is already refused
+        //by the runtime, see Pool::lock
+        self.pool.exit(&crate::ID)?;
+        let pool_token_in = if swap_token_0_for_1 {
+            &mut self.pool_token_0
+        } else {
+            &mut self.pool_token_1
+        };
+        let balance_before = pool_token_in.amount;
+        invoke_callback(
+            callback_program,
+            callback_accounts,
+            &FLASH_SWAP_CALLBACK_DISCRIMINATOR,
+            &FlashSwapCallbackArgs{
+                amount_owed,
+                amount_out : amount_out_received,
+                swap_token_0_for_1,
+                data
+            }
+        )?;
+        pool_token_in.reload()?;
+        require!(
+            pool_token_in.amount
+                >= balance_before.checked_add(amount_in).ok_or(ErrorCode::ArithmeticOverflow)?,
+            ErrorCode::InsufficientRepayment
+        );
+
+        self.pool.unlock();
+        Ok((amount_out_received, swap_result.fee_amount))
+    }
+}
+
+//what a swap did to the pool, amounts are what the vaults see, before any transfer fee on the way out
+pub struct SwapResult{
+    pub amount_out : u64,
+    pub fee_amount : u64,
+    pub ticks_crossed : u32
+}
+
+//runs the swap against the pool and its tick arrays and leaves the pool at the new price
+//with persist = false only the pool passed in changes and the tick arrays are only read,
+//that is how quote_swap runs the exact same loop without touching state
+#[allow(clippy::too_many_arguments)]
+pub fn swap_internal<'info>(
+    pool : &mut Account<'info, Pool>,
+    tick_array : &mut TickArray,
+    remaining_accounts : &'info [AccountInfo<'info>],
+    amount_in : u64,
+    swap_token_0_for_1 : bool,
+    cumulatives : &Observation,
+    config : Option<&GlobalConfig>,
+    persist : bool
+)->Result<SwapResult>{
+    require!(pool.global_liquidity >0,ErrorCode::InsufficentAmount);
+    let block_timestamp = Clock::get()?.unix_timestamp as u32;
+    if pool.dynamic_fee {
+        let config = config.ok_or(ErrorCode::MissingConfig)?;
+        pool.update_dynamic_fee(block_timestamp, config.min_fee_rate, config.max_fee_rate);
+    }
+
+    //the segment always consumes the whole input
+    let (_, amount_out, new_sqrt_price_x96, fee_amount) = swap_segment(
+        pool.sqrt_price_x96,
+        pool.global_liquidity,
+        amount_in,
+        swap_token_0_for_1,
+        pool.fee_rate,
+    )?;
+
+    //the fee is earned by the liquidity that was active during the segment, before any tick is crossed,
+    //limit orders do not share in it, with nothing but orders active it stays in the vault
+    let fee_liquidity = pool.fee_liquidity();
+    if fee_liquidity > 0 {
+        let fee_growth_delta = get_fee_growth_delta(fee_amount, fee_liquidity)?;
         if swap_token_0_for_1 {
-            token::transfer(
-                CpiContext::new(
-                    self.token_program.to_account_info(),
-                    Transfer {
-                        from: self.user_token_0.to_account_info(),
-                        to: self.pool_token_0.to_account_info(),
-                        authority: self.payer.to_account_info(),
-                    },
-                ),
-                amount_in_used,
-            )?;
-
-            token::transfer(
-                CpiContext::new_with_signer(
-                    self.token_program.to_account_info(),
-                    Transfer {
-                        from: self.pool_token_1.to_account_info(),
-                        to: self.user_token_1.to_account_info(),
-                        authority: pool.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                amount_out_calculated,
-            )?;
+            pool.fee_growth_global_0_x64 = pool.fee_growth_global_0_x64.wrapping_add(fee_growth_delta);
+        } else {
+            pool.fee_growth_global_1_x64 = pool.fee_growth_global_1_x64.wrapping_add(fee_growth_delta);
         }
-        else {
-            token::transfer(
-                CpiContext::new(
-                    self.token_program.to_account_info(),
-                    Transfer {
-                        from: self.user_token_1.to_account_info(),
-                        to: self.pool_token_1.to_account_info(),
-                        authority: self.payer.to_account_info(),
-                    },
-                ),
-                amount_in_used,
-            )?;
-
-            token::transfer(
-                CpiContext::new_with_signer(
-                    self.token_program.to_account_info(),
-                    Transfer {
-                        from: self.pool_token_0.to_account_info(),
-                        to: self.user_token_0.to_account_info(),
-                        authority: pool.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                amount_out_calculated,
-            )?;
+    }
+
+    let new_tick = get_tick_at_sqrt_price(new_sqrt_price_x96)?;
+    let (global_liquidity, limit_order_liquidity, ticks_crossed) = cross_tick_arrays(
+        pool,
+        tick_array,
+        remaining_accounts,
+        new_tick,
+        swap_token_0_for_1,
+        cumulatives,
+        persist
+    )?;
+    pool.global_liquidity = global_liquidity;
+    pool.limit_order_liquidity = limit_order_liquidity;
+    pool.sqrt_price_x96 = new_sqrt_price_x96;
+    pool.current_tick = new_tick;
+    pool.accumulate_volatility(block_timestamp, ticks_crossed);
+
+    Ok(SwapResult{
+        amount_out,
+        fee_amount,
+        ticks_crossed
+    })
+}
+
+//walks the tick arrays from the current one in swap direction up to new_tick and
+//returns the liquidity, and the limit order part of it, that is active once the price sits at new_tick
+//tick arrays after the current one are passed through remaining_accounts, in order
+//an array that was never created can be passed as its (empty) PDA
+fn cross_tick_arrays<'info>(
+    pool : &Account<'info, Pool>,
+    current_tick_array : &mut TickArray,
+    remaining_accounts : &'info [AccountInfo<'info>],
+    new_tick : i32,
+    swap_token_0_for_1 : bool,
+    cumulatives : &Observation,
+    persist : bool
+)->Result<(u128, u128, u32)>{
+    let span = TickArray::span(pool.tick_spacing);
+    let covers = |starting_tick : i32| {
+        if swap_token_0_for_1 {
+            starting_tick <= new_tick
+        } else {
+            starting_tick + span > new_tick
+        }
+    };
+
+    //crossing flips the fee growth outside of each tick and is written in place,
+    //a quote only needs the liquidity and leaves the arrays as they are
+    let (mut liquidity, mut limit_order_liquidity, mut ticks_crossed) = if persist {
+        current_tick_array.cross_ticks(
+            pool,
+            new_tick,
+            swap_token_0_for_1,
+            pool.global_liquidity,
+            pool.limit_order_liquidity,
+            cumulatives
+        )?
+    } else {
+        current_tick_array.quote_cross_ticks(
+            pool,
+            new_tick,
+            swap_token_0_for_1,
+            pool.global_liquidity,
+            pool.limit_order_liquidity
+        )?
+    };
+    let mut starting_tick = current_tick_array.starting_tick;
+    let mut accounts = remaining_accounts.iter();
+
+    while !covers(starting_tick) {
+        starting_tick = if swap_token_0_for_1 {
+            starting_tick - span
+        } else {
+            starting_tick + span
         };
-        pool.sqrt_price_x96 = new_sqrt_price_x96;
-        pool.current_tick = get_tick_at_sqrt_price(new_sqrt_price_x96)?;
+        let account_info = accounts.next().ok_or(ErrorCode::MissingTickArray)?;
 
-        Ok(amount_out_calculated)
+        if account_info.owner != &crate::ID {
+            let (expected, _) = Pubkey::find_program_address(
+                &[
+                    b"tick_array".as_ref(),
+                    pool.key().as_ref(),
+                    &starting_tick.to_le_bytes()
+                ],
+                &crate::ID
+            );
+            require_keys_eq!(account_info.key(), expected, ErrorCode::InvalidTickArrayAccount);
+            continue;
+        }
+        require!(account_info.is_writable || !persist, ErrorCode::InvalidTickArrayAccount);
+        let tick_array_loader = AccountLoader::<TickArray>::try_from(account_info)?;
+        let check = |tick_array : &TickArray|->Result<()>{
+            require!(
+                tick_array.pool == pool.key() && tick_array.starting_tick == starting_tick,
+                ErrorCode::InvalidTickArrayAccount
+            );
+            Ok(())
+        };
+        let (array_liquidity, array_limit_order_liquidity, array_ticks_crossed) = if persist {
+            let mut tick_array = tick_array_loader.load_mut()?;
+            check(&tick_array)?;
+            tick_array.cross_ticks(
+                pool,
+                new_tick,
+                swap_token_0_for_1,
+                liquidity,
+                limit_order_liquidity,
+                cumulatives
+            )?
+        } else {
+            let tick_array = tick_array_loader.load()?;
+            check(&tick_array)?;
+            tick_array.quote_cross_ticks(
+                pool,
+                new_tick,
+                swap_token_0_for_1,
+                liquidity,
+                limit_order_liquidity
+            )?
+        };
+        liquidity = array_liquidity;
+        limit_order_liquidity = array_limit_order_liquidity;
+        ticks_crossed += array_ticks_crossed;
     }
-    
-
-}
\ No newline at end of file
+    Ok((liquidity, limit_order_liquidity, ticks_crossed))
+}
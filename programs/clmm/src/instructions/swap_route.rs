@@ -0,0 +1,201 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{token::ID as TOKEN_PROGRAM_ID, token_2022::ID as TOKEN_2022_PROGRAM_ID, token_interface::{Mint, TokenAccount, TokenInterface}};
+use crate::{
+    events::SwapEvent,
+    instructions::swap_internal,
+    states::{GlobalConfig, ObservationState, Pool, TickArray, POOL_STATUS_SWAP_PAUSED},
+    utils::{get_transfer_fee, transfer_from_user_to_vault, transfer_from_vault_to_user}
+};
+use crate::utils::ErrorCode;
+
+//accounts every hop passes through remaining_accounts ahead of its tick arrays
+const ACCOUNTS_PER_HOP : usize = 6;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapRoute<'info>{
+    pub payer : Signer<'info>,
+    #[account(mut)]
+    pub user_token_in : InterfaceAccount<'info,TokenAccount>,
+    #[account(mut)]
+    pub user_token_out : InterfaceAccount<'info,TokenAccount>,
+    //a route can go through mints of either program, every transfer uses the one owning its mint
+    #[account(address = TOKEN_PROGRAM_ID)]
+    pub token_program : Interface<'info,TokenInterface>,
+    #[account(address = TOKEN_2022_PROGRAM_ID)]
+    pub token_program_2022 : Interface<'info,TokenInterface>,
+    //only needed when a pool on the route uses the dynamic fee, the config holds its bounds
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Option<Account<'info, GlobalConfig>>,
+}
+
+impl <'info> SwapRoute<'info>{
+    //every hop takes [pool, vault_in, vault_out, mint_in, mint_out, observation_state]
+    //followed by tick_array_counts[hop] tick arrays, the first one holding the current tick
+    //the output of a hop goes straight from its vault into the input vault of the next pool
+    pub fn new(
+        &mut self,
+        amount_in : u64,
+        amount_out_minimum : u64,
+        tick_array_counts : Vec<u8>,
+        remaining_accounts : &'info [AccountInfo<'info>]
+    )->Result<Vec<SwapEvent>>{
+        require!(amount_in >0,ErrorCode::InsufficentAmount);
+        require!(!tick_array_counts.is_empty(), ErrorCode::InvalidRoute);
+
+        let hops = tick_array_counts.len();
+        let mut events = Vec::with_capacity(hops);
+        let mut accounts = remaining_accounts;
+        let mut hop_amount_in = amount_in;
+        let mut previous_vault_out : Option<(Account<'info, Pool>, InterfaceAccount<'info, TokenAccount>, InterfaceAccount<'info, Mint>)> = None;
+
+        for (hop, tick_array_count) in tick_array_counts.iter().enumerate() {
+            let tick_array_count = *tick_array_count as usize;
+            require!(tick_array_count > 0, ErrorCode::MissingTickArray);
+            require!(accounts.len() >= ACCOUNTS_PER_HOP + tick_array_count, ErrorCode::InvalidRoute);
+            let (hop_accounts, rest) = accounts.split_at(ACCOUNTS_PER_HOP + tick_array_count);
+            accounts = rest;
+
+            require!(hop_accounts[0].is_writable, ErrorCode::InvalidRoute);
+            let mut pool = Account::<Pool>::try_from(&hop_accounts[0])?;
+            let vault_in = InterfaceAccount::<TokenAccount>::try_from(&hop_accounts[1])?;
+            let vault_out = InterfaceAccount::<TokenAccount>::try_from(&hop_accounts[2])?;
+            let mint_in = InterfaceAccount::<Mint>::try_from(&hop_accounts[3])?;
+            let mint_out = InterfaceAccount::<Mint>::try_from(&hop_accounts[4])?;
+            let observation_state = AccountLoader::<ObservationState>::try_from(&hop_accounts[5])?;
+            let tick_array_loader = AccountLoader::<TickArray>::try_from(&hop_accounts[ACCOUNTS_PER_HOP])?;
+            let mut tick_array = tick_array_loader.load_mut()?;
+            let extra_tick_arrays = &hop_accounts[ACCOUNTS_PER_HOP + 1..];
+
+            require!(!pool.is_paused(POOL_STATUS_SWAP_PAUSED), ErrorCode::OperationPaused);
+            pool.lock()?;
+            let swap_token_0_for_1 = mint_in.key() == pool.token_mint_0;
+            let (expected_mint_out, expected_vault_in, expected_vault_out) = if swap_token_0_for_1 {
+                (pool.token_mint_1, pool.token_vault_0, pool.token_vault_1)
+            } else {
+                require_keys_eq!(mint_in.key(), pool.token_mint_1, ErrorCode::InvalidMint);
+                (pool.token_mint_0, pool.token_vault_1, pool.token_vault_0)
+            };
+            require_keys_eq!(mint_out.key(), expected_mint_out, ErrorCode::InvalidMint);
+            require_keys_eq!(vault_in.key(), expected_vault_in, ErrorCode::InvalidVault);
+            require_keys_eq!(vault_out.key(), expected_vault_out, ErrorCode::InvalidVault);
+            let token_program_in = self.token_program_for(&mint_in)?;
+            let token_program_out = self.token_program_for(&mint_out)?;
+            require_keys_eq!(
+                observation_state.key(),
+                Pubkey::create_program_address(
+                    &[b"observation", pool.key().as_ref(), &[observation_state.load()?.bump]],
+                    &crate::ID
+                ).map_err(|_| ErrorCode::InvalidRoute)?,
+                ErrorCode::InvalidRoute
+            );
+            require!(
+                hop_accounts[ACCOUNTS_PER_HOP].is_writable
+                    && tick_array.pool == pool.key()
+                    && tick_array.starting_tick == TickArray::get_starting_tick_index(pool.current_tick, pool.tick_spacing),
+                ErrorCode::InvalidTickArrayAccount
+            );
+
+            //move the input into this pool, from the user on the first hop and from the previous pool after that
+            let amount_in_received = hop_amount_in
+                .checked_sub(get_transfer_fee(&mint_in, hop_amount_in)?)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            match previous_vault_out.take() {
+                None => {
+                    require_keys_eq!(self.user_token_in.mint, mint_in.key(), ErrorCode::InvalidMint);
+                    transfer_from_user_to_vault(
+                        &self.payer,
+                        &self.user_token_in,
+                        &vault_in,
+                        &mint_in,
+                        token_program_in,
+                        hop_amount_in
+                    )?;
+                }
+                Some((previous_pool, previous_vault, previous_mint)) => {
+                    require_keys_eq!(previous_mint.key(), mint_in.key(), ErrorCode::InvalidRoute);
+                    transfer_from_vault_to_user(
+                        &previous_pool,
+                        &previous_vault,
+                        &vault_in,
+                        &mint_in,
+                        token_program_in,
+                        hop_amount_in
+                    )?;
+                }
+            }
+
+            let block_timestamp = Clock::get()?.unix_timestamp as u32;
+            let cumulatives = ObservationState::record(
+                &observation_state,
+                block_timestamp,
+                pool.current_tick,
+                pool.global_liquidity
+            )?;
+            let swap_result = swap_internal(
+                &mut pool,
+                &mut tick_array,
+                extra_tick_arrays,
+                amount_in_received,
+                swap_token_0_for_1,
+                &cumulatives,
+                self.config.as_deref(),
+                true
+            )?;
+            pool.unlock();
+            pool.exit(&crate::ID)?;
+            drop(tick_array);
+
+            events.push(SwapEvent{
+                pool : pool.key(),
+                sender : self.payer.key(),
+                swap_token_0_for_1,
+                amount_in : hop_amount_in,
+                amount_out : swap_result.amount_out,
+                fee_amount : swap_result.fee_amount,
+                referral_fee : 0,
+                sqrt_price_x96 : pool.sqrt_price_x96,
+                tick : pool.current_tick,
+                liquidity : pool.global_liquidity
+            });
+
+            if hop + 1 == hops {
+                require_keys_eq!(self.user_token_out.mint, mint_out.key(), ErrorCode::InvalidMint);
+                let amount_out_received = swap_result.amount_out
+                    .checked_sub(get_transfer_fee(&mint_out, swap_result.amount_out)?)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+                require!(
+                    amount_out_received >= amount_out_minimum,
+                    ErrorCode::SlippageExceeded
+                );
+                transfer_from_vault_to_user(
+                    &pool,
+                    &vault_out,
+                    &self.user_token_out,
+                    &mint_out,
+                    token_program_out,
+                    swap_result.amount_out
+                )?;
+            } else {
+                hop_amount_in = swap_result.amount_out;
+                previous_vault_out = Some((pool, vault_out, mint_out));
+            }
+        }
+        require!(accounts.is_empty(), ErrorCode::InvalidRoute);
+        Ok(events)
+    }
+
+    fn token_program_for(&self, mint : &InterfaceAccount<'info, Mint>)->Result<&Interface<'info,TokenInterface>>{
+        let owner = *mint.to_account_info().owner;
+        if owner == self.token_program.key() {
+            Ok(&self.token_program)
+        } else if owner == self.token_program_2022.key() {
+            Ok(&self.token_program_2022)
+        } else {
+            err!(ErrorCode::InvalidMint)
+        }
+    }
+}
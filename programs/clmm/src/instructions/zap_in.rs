@@ -0,0 +1,396 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{
+    instructions::swap_internal,
+    states::{
+        modify_position, GlobalConfig, Observation, ObservationState, Pool, Position, TickArray,
+        POOL_STATUS_DEPOSIT_PAUSED, POOL_STATUS_SWAP_PAUSED
+    },
+    utils::{
+        get_amount_for_liquidity, get_liquidity_for_amounts, get_sqrt_price_from_tick, get_transfer_fee,
+        transfer_from_user_to_vault, transfer_from_vault_to_user, MAX_TICK, MIN_TICK
+    }
+};
+use crate::utils::ErrorCode;
+
+//liquidity the swap split is priced with, only the ratio of the amounts it needs matters
+const RATIO_LIQUIDITY : u128 = 1 << 32;
+//bisecting a u64 amount never takes more than this
+const MAX_SEARCH_STEPS : u32 = 64;
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(
+    amount_in : u64,
+    input_token_0 : bool,
+    lower_tick : i32,
+    uppar_tick : i32,
+    min_liquidity : u128,
+    tick_array_lower_start_index : i32,
+    tick_array_uppar_start_index : i32
+)]
+pub struct ZapIn<'info>{
+    #[account(mut)]
+    pub payer : Signer<'info>,
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
+    pub pool : Account<'info, Pool>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = TickArray::SPACE,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &tick_array_lower_start_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = TickArray::SPACE,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &tick_array_uppar_start_index.to_le_bytes()
+        ],
+        bump
+    )]
+    pub uppar_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Position::INIT_SPACE,
+        seeds = [
+            b"position",
+            payer.key().as_ref(),
+            pool.key().as_ref(),
+            &lower_tick.to_le_bytes(),
+            &uppar_tick.to_le_bytes()
+        ],
+        bump
+    )]
+    pub position : Account<'info, Position>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
+    )]
+    pub user_token_0 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
+    )]
+    pub user_token_1 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_0 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_1 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
+    pub system_program : Program<'info,System>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>,
+    //only needed when the pool uses the dynamic fee, the config holds its bounds
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Option<Account<'info, GlobalConfig>>,
+}
+
+//what the zap did, dust is what could not be deposited and went back to the user
+pub struct ZapInResult{
+    pub liquidity : u128,
+    pub swap_amount_in : u64,
+    pub swap_amount_out : u64,
+    pub dust_0 : u64,
+    pub dust_1 : u64
+}
+
+impl <'info> ZapIn<'info>{
+    //takes amount_in of a single token, swaps part of it through this pool and deposits both
+    //sides into the payer's position, opening it if needed
+    //remaining_accounts are the tick arrays of the swap, the first one holding the current tick
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        &mut self,
+        amount_in : u64,
+        input_token_0 : bool,
+        lower_tick : i32,
+        uppar_tick : i32,
+        min_liquidity : u128,
+        tick_array_lower_start_index : i32,
+        tick_array_uppar_start_index : i32,
+        bump : u8,
+        remaining_accounts : &'info [AccountInfo<'info>]
+    )->Result<ZapInResult>{
+        require!(!self.pool.is_paused(POOL_STATUS_DEPOSIT_PAUSED), ErrorCode::OperationPaused);
+        self.pool.lock()?;
+        require!(amount_in > 0, ErrorCode::InsufficentAmount);
+        let tick_spacing = self.pool.tick_spacing;
+        require!(
+            lower_tick < uppar_tick
+                && lower_tick >= MIN_TICK
+                && uppar_tick <= MAX_TICK
+                && lower_tick % tick_spacing == 0
+                && uppar_tick % tick_spacing == 0,
+            ErrorCode::InvalidTickRange
+        );
+        let lower_sqrt_price_x96 = get_sqrt_price_from_tick(lower_tick)?;
+        let upper_sqrt_price_x96 = get_sqrt_price_from_tick(uppar_tick)?;
+
+        //the whole input goes into the vault first, the swap and the deposit both happen inside it
+        let (mint_in, user_token_in, pool_token_in, token_program_in) = if input_token_0 {
+            (&self.token_mint_0, &self.user_token_0, &self.pool_token_0, &self.token_program_0)
+        } else {
+            (&self.token_mint_1, &self.user_token_1, &self.pool_token_1, &self.token_program_1)
+        };
+        transfer_from_user_to_vault(
+            &self.payer,
+            user_token_in,
+            pool_token_in,
+            mint_in,
+            token_program_in,
+            amount_in
+        )?;
+        let amount_in_received = amount_in
+            .checked_sub(get_transfer_fee(mint_in, amount_in)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let (current_tick_array_info, tick_arrays) = remaining_accounts
+            .split_first()
+            .ok_or(ErrorCode::MissingTickArray)?;
+        let current_tick_array_loader = AccountLoader::<TickArray>::try_from(current_tick_array_info)?;
+        let mut current_tick_array = current_tick_array_loader.load_mut()?;
+        require!(
+            current_tick_array_info.is_writable
+                && current_tick_array.pool == self.pool.key()
+                && current_tick_array.starting_tick
+                    == TickArray::get_starting_tick_index(self.pool.current_tick, tick_spacing),
+            ErrorCode::InvalidTickArrayAccount
+        );
+
+        let swap_amount_in = find_swap_amount(
+            &self.pool,
+            &current_tick_array,
+            tick_arrays,
+            self.config.as_deref(),
+            amount_in_received,
+            input_token_0,
+            lower_sqrt_price_x96,
+            upper_sqrt_price_x96
+        )?;
+        let swap_amount_out = if swap_amount_in > 0 {
+            require!(!self.pool.is_paused(POOL_STATUS_SWAP_PAUSED), ErrorCode::OperationPaused);
+            let block_timestamp = Clock::get()?.unix_timestamp as u32;
+            let cumulatives = ObservationState::record(
+                &self.observation_state,
+                block_timestamp,
+                self.pool.current_tick,
+                self.pool.global_liquidity
+            )?;
+            let swap_result = swap_internal(
+                &mut self.pool,
+                &mut current_tick_array,
+                tick_arrays,
+                swap_amount_in,
+                input_token_0,
+                &cumulatives,
+                self.config.as_deref(),
+                true
+            )?;
+            swap_result.amount_out
+        } else {
+            0
+        };
+        //the position arrays may be among the swap arrays, they are only loaded once it is done
+        drop(current_tick_array);
+
+        let amount_left = amount_in_received - swap_amount_in;
+        let (amount_0, amount_1) = if input_token_0 {
+            (amount_left, swap_amount_out)
+        } else {
+            (swap_amount_out, amount_left)
+        };
+        let pool = &mut self.pool;
+        let liquidity = get_liquidity_for_amounts(
+            pool.sqrt_price_x96,
+            lower_sqrt_price_x96,
+            upper_sqrt_price_x96,
+            amount_0,
+            amount_1
+        );
+        require!(liquidity > 0 && liquidity >= min_liquidity, ErrorCode::SlippageExceeded);
+        let (used_0, used_1) = get_amount_for_liquidity(
+            pool.sqrt_price_x96,
+            lower_sqrt_price_x96,
+            upper_sqrt_price_x96,
+            liquidity
+        )?;
+
+        let (mut lower_tick_array, mut uppar_tick_array) =
+            TickArray::load_range_mut(&self.lower_tick_array, &self.uppar_tick_array)?;
+        //None when both ticks sit in the lower array, it is set up once
+        for (tick_array, starting_tick) in [
+            (Some(&mut *lower_tick_array), tick_array_lower_start_index),
+            (uppar_tick_array.as_deref_mut(), tick_array_uppar_start_index)
+        ] {
+            let Some(tick_array) = tick_array else { continue };
+            if tick_array.starting_tick == 0 && tick_array.pool == Pubkey::default() {
+                tick_array.pool = pool.key();
+                tick_array.starting_tick = starting_tick;
+                tick_array.funder = self.payer.key();
+            }
+        }
+        let position = &mut self.position;
+        if position.liquidity == 0 && position.owner == Pubkey::default() {
+            position.owner = self.payer.key();
+            position.pool = pool.key();
+            position.tick_lower = lower_tick;
+            position.tick_uppar = uppar_tick;
+            position.bump = bump;
+        }
+
+        let block_timestamp = Clock::get()?.unix_timestamp as u32;
+        let cumulatives = ObservationState::record(
+            &self.observation_state,
+            block_timestamp,
+            pool.current_tick,
+            pool.global_liquidity
+        )?;
+        modify_position(
+            pool,
+            position,
+            &mut lower_tick_array,
+            uppar_tick_array.as_deref_mut(),
+            liquidity as i128,
+            Some(&cumulatives)
+        )?;
+
+        let dust_0 = amount_0 - used_0;
+        let dust_1 = amount_1 - used_1;
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_0,
+            &self.user_token_0,
+            &self.token_mint_0,
+            &self.token_program_0,
+            dust_0
+        )?;
+        transfer_from_vault_to_user(
+            &self.pool,
+            &self.pool_token_1,
+            &self.user_token_1,
+            &self.token_mint_1,
+            &self.token_program_1,
+            dust_1
+        )?;
+
+        self.pool.unlock();
+        Ok(ZapInResult{
+            liquidity,
+            swap_amount_in,
+            swap_amount_out,
+            dust_0,
+            dust_1
+        })
+    }
+}
+
+//how much of amount to swap so that what is left of it and the swap output come in the ratio
+//the range asks for at the price the swap leaves behind
+//every candidate is priced with a quote of the real swap, a larger swap leaves less input and
+//more output at a price that asks for less output, so the smallest amount that is enough is
+//found by bisecting [1, amount], which takes at most MAX_SEARCH_STEPS quotes
+#[allow(clippy::too_many_arguments)]
+fn find_swap_amount<'info>(
+    pool : &Account<'info, Pool>,
+    tick_array : &TickArray,
+    tick_arrays : &'info [AccountInfo<'info>],
+    config : Option<&GlobalConfig>,
+    amount : u64,
+    swap_token_0_for_1 : bool,
+    lower_sqrt_price_x96 : u128,
+    upper_sqrt_price_x96 : u128
+)->Result<u64>{
+    //input/output amounts the range takes per RATIO_LIQUIDITY at a price
+    let ratio = |sqrt_price_x96 : u128|->Result<(u128, u128)>{
+        let (ratio_0, ratio_1) = get_amount_for_liquidity(
+            sqrt_price_x96,
+            lower_sqrt_price_x96,
+            upper_sqrt_price_x96,
+            RATIO_LIQUIDITY
+        )?;
+        Ok(if swap_token_0_for_1 {
+            (ratio_0 as u128, ratio_1 as u128)
+        } else {
+            (ratio_1 as u128, ratio_0 as u128)
+        })
+    };
+
+    //the range only takes the input token at the current price, no swap needed
+    if ratio(pool.sqrt_price_x96)?.1 == 0 {
+        return Ok(0);
+    }
+    //quotes only read the arrays, one copy of the current one serves all of them
+    let mut quoted_tick_array = tick_array.boxed_copy();
+    //what is left of the input can be paired with the output of swapping swap_amount_in
+    let mut enough = |swap_amount_in : u64|->Result<bool>{
+        let mut quoted_pool = pool.clone();
+        let swap_result = swap_internal(
+            &mut quoted_pool,
+            &mut quoted_tick_array,
+            tick_arrays,
+            swap_amount_in,
+            swap_token_0_for_1,
+            &Observation::default(),
+            config,
+            false
+        )?;
+        let (ratio_in, ratio_out) = ratio(quoted_pool.sqrt_price_x96)?;
+        Ok((amount - swap_amount_in) as u128 * ratio_out <= swap_result.amount_out as u128 * ratio_in)
+    };
+
+    //swapping everything leaves nothing to pair, so amount is always enough
+    let (mut low, mut high) = (1, amount);
+    for _ in 0..MAX_SEARCH_STEPS {
+        if low >= high {
+            break;
+        }
+        let middle = low + (high - low) / 2;
+        if enough(middle)? {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Ok(high)
+}
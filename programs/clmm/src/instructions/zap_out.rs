@@ -0,0 +1,236 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{
+    instructions::swap_internal,
+    states::{
+        modify_position, GlobalConfig, ObservationState, Pool, Position, TickArray,
+        POOL_STATUS_SWAP_PAUSED, POOL_STATUS_WITHDRAW_PAUSED
+    },
+    utils::{get_amount_for_liquidity, get_sqrt_price_from_tick, get_transfer_fee, transfer_from_vault_to_user}
+};
+use crate::utils::ErrorCode;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ZapOut<'info>{
+    #[account(mut)]
+    pub payer : Signer<'info>,
+    #[account(
+        mut,
+        has_one = token_mint_0 @ErrorCode::InvalidMint,
+        has_one = token_mint_1 @ErrorCode::InvalidMint
+    )]
+    pub pool : Account<'info, Pool>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_lower, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub lower_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            &TickArray::get_starting_tick_index(position.tick_uppar, pool.tick_spacing).to_le_bytes()
+        ],
+        bump
+    )]
+    pub uppar_tick_array : AccountLoader<'info, TickArray>,
+    #[account(
+        mut,
+        has_one = pool @ErrorCode::InvalidPositionRange,
+        constraint = position.owner == payer.key() @ErrorCode::Unauthorized
+    )]
+    pub position : Account<'info, Position>,
+    #[account(
+        mut,
+        seeds = [
+            b"observation",
+            pool.key().as_ref()
+        ],
+        bump = observation_state.load()?.bump
+    )]
+    pub observation_state : AccountLoader<'info, ObservationState>,
+    #[account(
+        mut,
+        token::mint = token_mint_0,
+        token::token_program = token_program_0
+    )]
+    pub user_token_0 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_mint_1,
+        token::token_program = token_program_1
+    )]
+    pub user_token_1 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_0 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_0 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = pool.token_vault_1 @ErrorCode::InvalidVault
+    )]
+    pub pool_token_1 : InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mint::token_program = token_program_0
+    )]
+    pub token_mint_0 : InterfaceAccount<'info,Mint>,
+    #[account(
+        mint::token_program = token_program_1
+    )]
+    pub token_mint_1 : InterfaceAccount<'info,Mint>,
+    pub token_program_0 : Interface<'info,TokenInterface>,
+    pub token_program_1 : Interface<'info,TokenInterface>,
+    //only needed when the pool uses the dynamic fee, the config holds its bounds
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config : Option<Account<'info, GlobalConfig>>,
+}
+
+//what the zap did, amount_0/amount_1 are what the position gave up before the swap
+pub struct ZapOutResult{
+    pub amount_0 : u64,
+    pub amount_1 : u64,
+    pub swap_amount_in : u64,
+    pub amount_out : u64
+}
+
+impl <'info> ZapOut<'info>{
+    //withdraws liquidity_amount from the payer's position and swaps the side the payer does not
+    //want through this pool, so only one token leaves the vault, closing the position if asked
+    //remaining_accounts are the tick arrays of the swap, the first one holding the current tick
+    pub fn new(
+        &mut self,
+        liquidity_amount : u128,
+        output_token_0 : bool,
+        amount_out_minimum : u64,
+        close_position : bool,
+        remaining_accounts : &'info [AccountInfo<'info>]
+    )->Result<ZapOutResult>{
+        require!(!self.pool.is_paused(POOL_STATUS_WITHDRAW_PAUSED), ErrorCode::OperationPaused);
+        self.pool.lock()?;
+        require!(liquidity_amount > 0, ErrorCode::InsufficentAmount);
+        let pool = &mut self.pool;
+        let position = &mut self.position;
+
+        let (amount_0, amount_1) = get_amount_for_liquidity(
+            pool.sqrt_price_x96,
+            get_sqrt_price_from_tick(position.tick_lower)?,
+            get_sqrt_price_from_tick(position.tick_uppar)?,
+            liquidity_amount
+        )?;
+        //written before the liquidity changes, so the last interval is weighted with the old value
+        ObservationState::record(&self.observation_state, Clock::get()?.unix_timestamp as u32, pool.current_tick, pool.global_liquidity)?;
+        //the swap may go through the position's arrays, they are released before it loads them
+        {
+            let (mut lower_tick_array, mut upper_tick_array) =
+                TickArray::load_range_mut(&self.lower_tick_array, &self.uppar_tick_array)?;
+            modify_position(
+                pool,
+                position,
+                &mut lower_tick_array,
+                upper_tick_array.as_deref_mut(),
+                -(liquidity_amount as i128),
+                None
+            )?;
+        }
+        //a closed account can not hold uncollected fees, they are zapped out with the liquidity
+        let (amount_0, amount_1) = if close_position {
+            require!(position.liquidity == 0, ErrorCode::PositionNotEmpty);
+            let amount_0 = amount_0
+                .checked_add(position.tokens_owed_0)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            let amount_1 = amount_1
+                .checked_add(position.tokens_owed_1)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            position.tokens_owed_0 = 0;
+            position.tokens_owed_1 = 0;
+            (amount_0, amount_1)
+        } else {
+            (amount_0, amount_1)
+        };
+        //the other side never leaves the vault, it is the input of the swap
+        let (swap_amount_in, amount_kept) = if output_token_0 {
+            (amount_1, amount_0)
+        } else {
+            (amount_0, amount_1)
+        };
+        let swap_amount_out = if swap_amount_in > 0 {
+            require!(!self.pool.is_paused(POOL_STATUS_SWAP_PAUSED), ErrorCode::OperationPaused);
+            let (current_tick_array_info, tick_arrays) = remaining_accounts
+                .split_first()
+                .ok_or(ErrorCode::MissingTickArray)?;
+            let current_tick_array_loader = AccountLoader::<TickArray>::try_from(current_tick_array_info)?;
+            let mut current_tick_array = current_tick_array_loader.load_mut()?;
+            require!(
+                current_tick_array_info.is_writable
+                    && current_tick_array.pool == self.pool.key()
+                    && current_tick_array.starting_tick
+                        == TickArray::get_starting_tick_index(self.pool.current_tick, self.pool.tick_spacing),
+                ErrorCode::InvalidTickArrayAccount
+            );
+            let block_timestamp = Clock::get()?.unix_timestamp as u32;
+            let cumulatives = ObservationState::record(
+                &self.observation_state,
+                block_timestamp,
+                self.pool.current_tick,
+                self.pool.global_liquidity
+            )?;
+            let swap_result = swap_internal(
+                &mut self.pool,
+                &mut current_tick_array,
+                tick_arrays,
+                swap_amount_in,
+                !output_token_0,
+                &cumulatives,
+                self.config.as_deref(),
+                true
+            )?;
+            swap_result.amount_out
+        } else {
+            0
+        };
+
+        let (pool_token_out, user_token_out, mint_out, token_program_out) = if output_token_0 {
+            (&self.pool_token_0, &self.user_token_0, &self.token_mint_0, &self.token_program_0)
+        } else {
+            (&self.pool_token_1, &self.user_token_1, &self.token_mint_1, &self.token_program_1)
+        };
+        let amount_out = amount_kept
+            .checked_add(swap_amount_out)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        //one guard for both steps, on what actually reaches the user
+        let amount_out_received = amount_out
+            .checked_sub(get_transfer_fee(mint_out, amount_out)?)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(amount_out_received >= amount_out_minimum, ErrorCode::SlippageExceeded);
+        transfer_from_vault_to_user(
+            &self.pool,
+            pool_token_out,
+            user_token_out,
+            mint_out,
+            token_program_out,
+            amount_out
+        )?;
+
+        self.pool.unlock();
+        if close_position {
+            self.position.close(self.payer.to_account_info())?;
+        }
+        Ok(ZapOutResult{
+            amount_0,
+            amount_1,
+            swap_amount_in,
+            amount_out
+        })
+    }
+}
@@ -1,8 +1,12 @@
+#![allow(clippy::new_ret_no_self)]
 use anchor_lang::prelude::*;
 pub mod states;
 pub mod instructions;
 pub mod utils;
+pub mod events;
 pub use instructions::*;
+use events::*;
+use states::ObserveResult;
 
 
 declare_id!("6kaKTU4t5TcvmFotq62EGxs8yLd4DzxiDHhUzf1Y1Xeq");
@@ -17,7 +21,22 @@ pub mod clmm {
         tick_spacing:i32,
         initial_sqrt_price : u128
     )->Result<()>{
-        ctx.accounts.new(tick_spacing, initial_sqrt_price,ctx.bumps.pool)?;
+        ctx.accounts.new(
+            tick_spacing,
+            initial_sqrt_price,
+            ctx.bumps.pool,
+            ctx.bumps.observation_state
+        )?;
+        let pool = &ctx.accounts.pool;
+        emit_cpi!(PoolInitialized{
+            pool : pool.key(),
+            token_mint_0 : pool.token_mint_0,
+            token_mint_1 : pool.token_mint_1,
+            tick_spacing : pool.tick_spacing,
+            sqrt_price_x96 : pool.sqrt_price_x96,
+            tick : pool.current_tick,
+            fee_rate : pool.fee_rate
+        });
         Ok(())
     }
     pub fn open_position(
@@ -29,7 +48,9 @@ pub mod clmm {
         _tick_array_lower_start_index : i32,
         _tick_array_uppar_start_index : i32
     )->Result<()>{
-        ctx.accounts.new(
+        //opening a range the owner already holds only adds to that position
+        let is_new = ctx.accounts.position.owner == Pubkey::default();
+        let (amount_0, amount_1) = ctx.accounts.new(
             owner, 
             lower_tick, 
             uppar_tick,
@@ -38,6 +59,369 @@ pub mod clmm {
             _tick_array_uppar_start_index,
             ctx.bumps.position
         )?;
+        if is_new {
+            emit_cpi!(PositionOpened{
+                pool : ctx.accounts.pool.key(),
+                position : ctx.accounts.position.key(),
+                owner,
+                tick_lower : lower_tick,
+                tick_upper : uppar_tick,
+                liquidity : liquidity_amount,
+                amount_0,
+                amount_1
+            });
+        } else {
+            emit_cpi!(LiquidityIncreased{
+                pool : ctx.accounts.pool.key(),
+                position : ctx.accounts.position.key(),
+                liquidity : liquidity_amount,
+                amount_0,
+                amount_1
+            });
+        }
+        Ok(())
+    }
+    pub fn increase_liquidity(
+        ctx: Context<IncreaseLiquidity>,
+        liquidity_amount : u128,
+        lower_tick : i32,
+        uppar_tick : i32
+    )->Result<()>{
+        let (amount_0, amount_1) = ctx.accounts.new(liquidity_amount, lower_tick, uppar_tick)?;
+        emit_cpi!(LiquidityIncreased{
+            pool : ctx.accounts.pool.key(),
+            position : ctx.accounts.position.key(),
+            liquidity : liquidity_amount,
+            amount_0,
+            amount_1
+        });
+        Ok(())
+    }
+    pub fn decrease_liquidity(
+        ctx: Context<DecreaseLiquidity>,
+        liquidity_amount : u128,
+        lower_tick : i32,
+        uppar_tick : i32
+    )->Result<()>{
+        let (amount_0, amount_1) = ctx.accounts.new(liquidity_amount, lower_tick, uppar_tick)?;
+        emit_cpi!(LiquidityDecreased{
+            pool : ctx.accounts.pool.key(),
+            position : ctx.accounts.position.key(),
+            liquidity : liquidity_amount,
+            amount_0,
+            amount_1
+        });
+        Ok(())
+    }
+    pub fn close_position(
+        ctx: Context<ClosePosition>,
+        lower_tick : i32,
+        uppar_tick : i32,
+        tick_array_lower_start_index : i32,
+        tick_array_uppar_start_index : i32
+    )->Result<()>{
+        let liquidity = ctx.accounts.position.liquidity;
+        let (amount_0, amount_1) = ctx.accounts.new(
+            lower_tick,
+            uppar_tick,
+            tick_array_lower_start_index,
+            tick_array_uppar_start_index
+        )?;
+        emit_cpi!(PositionClosed{
+            pool : ctx.accounts.pool.key(),
+            position : ctx.accounts.position.key(),
+            owner : ctx.accounts.owner.key(),
+            liquidity,
+            amount_0,
+            amount_1
+        });
+        Ok(())
+    }
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+        amount_in : u64,
+        swap_token_0_for_1 : bool,
+        amount_out_minimum : u64,
+        referral_fee_bps : u16
+    )->Result<()>{
+        let (amount_out, fee_amount, referral_fee) = ctx.accounts.new(
+            amount_in,
+            swap_token_0_for_1,
+            amount_out_minimum,
+            referral_fee_bps,
+            ctx.remaining_accounts
+        )?;
+        let pool = &ctx.accounts.pool;
+        emit_cpi!(SwapEvent{
+            pool : pool.key(),
+            sender : ctx.accounts.payer.key(),
+            swap_token_0_for_1,
+            amount_in,
+            amount_out,
+            fee_amount,
+            referral_fee,
+            sqrt_price_x96 : pool.sqrt_price_x96,
+            tick : pool.current_tick,
+            liquidity : pool.global_liquidity
+        });
+        Ok(())
+    }
+    pub fn close_tick_array(
+        ctx: Context<CloseTickArray>,
+        _tick_array_start_index : i32
+    )->Result<()>{
+        ctx.accounts.new()?;
+        Ok(())
+    }
+    pub fn collect_fees(ctx: Context<CollectFees>)->Result<()>{
+        let (amount_0, amount_1) = ctx.accounts.new()?;
+        emit_cpi!(FeesCollected{
+            pool : ctx.accounts.pool.key(),
+            position : ctx.accounts.position.key(),
+            owner : ctx.accounts.payer.key(),
+            amount_0,
+            amount_1
+        });
+        Ok(())
+    }
+    pub fn initialize_config(ctx: Context<InitializeConfig>, admin : Pubkey)->Result<()>{
+        ctx.accounts.new(admin, ctx.bumps.config)?;
+        Ok(())
+    }
+    pub fn propose_admin(ctx: Context<ProposeAdmin>, new_admin : Pubkey)->Result<()>{
+        ctx.accounts.new(new_admin)?;
+        Ok(())
+    }
+    pub fn accept_admin(ctx: Context<AcceptAdmin>)->Result<()>{
+        ctx.accounts.new()?;
+        Ok(())
+    }
+    pub fn set_pool_status(ctx: Context<SetPoolStatus>, status : u8)->Result<()>{
+        ctx.accounts.new(status)?;
+        Ok(())
+    }
+    pub fn set_fee_bounds(ctx: Context<SetFeeBounds>, min_fee_rate : u32, max_fee_rate : u32)->Result<()>{
+        ctx.accounts.new(min_fee_rate, max_fee_rate)?;
+        Ok(())
+    }
+    pub fn set_max_referral_fee(ctx: Context<SetMaxReferralFee>, max_referral_fee_bps : u16)->Result<()>{
+        ctx.accounts.new(max_referral_fee_bps)?;
+        Ok(())
+    }
+    pub fn set_keeper_bounty(ctx: Context<SetKeeperBounty>, keeper_bounty_bps : u16)->Result<()>{
+        ctx.accounts.new(keeper_bounty_bps)?;
+        Ok(())
+    }
+    pub fn set_dynamic_fee(ctx: Context<SetDynamicFee>, enabled : bool)->Result<()>{
+        ctx.accounts.new(enabled)?;
+        Ok(())
+    }
+    pub fn observe(ctx: Context<Observe>, seconds_agos : Vec<u32>)->Result<ObserveResult>{
+        ctx.accounts.new(seconds_agos)
+    }
+    pub fn increase_observation_cardinality(
+        ctx: Context<IncreaseObservationCardinality>,
+        observation_cardinality_next : u32
+    )->Result<()>{
+        ctx.accounts.new(observation_cardinality_next)?;
+        Ok(())
+    }
+    pub fn snapshot_cumulatives_inside(
+        ctx: Context<SnapshotCumulativesInside>,
+        tick_lower : i32,
+        tick_upper : i32
+    )->Result<CumulativesInside>{
+        ctx.accounts.new(tick_lower, tick_upper)
+    }
+    pub fn quote_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, QuoteSwap<'info>>,
+        amount_in : u64,
+        swap_token_0_for_1 : bool
+    )->Result<SwapQuote>{
+        ctx.accounts.new(amount_in, swap_token_0_for_1, ctx.remaining_accounts)
+    }
+    pub fn swap_route<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapRoute<'info>>,
+        amount_in : u64,
+        amount_out_minimum : u64,
+        tick_array_counts : Vec<u8>
+    )->Result<()>{
+        let events = ctx.accounts.new(
+            amount_in,
+            amount_out_minimum,
+            tick_array_counts,
+            ctx.remaining_accounts
+        )?;
+        for event in events {
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+    pub fn flash_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+        amount_in : u64,
+        swap_token_0_for_1 : bool,
+        amount_out_minimum : u64,
+        tick_array_count : u8,
+        data : Vec<u8>
+    )->Result<()>{
+        let (amount_out, fee_amount) = ctx.accounts.flash_swap(
+            amount_in,
+            swap_token_0_for_1,
+            amount_out_minimum,
+            tick_array_count,
+            data,
+            ctx.remaining_accounts
+        )?;
+        let pool = &ctx.accounts.pool;
+        emit_cpi!(SwapEvent{
+            pool : pool.key(),
+            sender : ctx.accounts.payer.key(),
+            swap_token_0_for_1,
+            amount_in,
+            amount_out,
+            fee_amount,
+            referral_fee : 0,
+            sqrt_price_x96 : pool.sqrt_price_x96,
+            tick : pool.current_tick,
+            liquidity : pool.global_liquidity
+        });
+        Ok(())
+    }
+    pub fn flash<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Flash<'info>>,
+        amount_0 : u64,
+        amount_1 : u64,
+        data : Vec<u8>
+    )->Result<()>{
+        let (paid_0, paid_1) = ctx.accounts.new(amount_0, amount_1, data, ctx.remaining_accounts)?;
+        emit_cpi!(FlashLoan{
+            pool : ctx.accounts.pool.key(),
+            sender : ctx.accounts.payer.key(),
+            amount_0,
+            amount_1,
+            paid_0,
+            paid_1
+        });
+        Ok(())
+    }
+    pub fn place_limit_order(
+        ctx: Context<PlaceLimitOrder>,
+        tick : i32,
+        sell_token_0 : bool,
+        liquidity_amount : u128,
+        tick_array_start_index : i32
+    )->Result<()>{
+        let amount = ctx.accounts.new(
+            tick,
+            sell_token_0,
+            liquidity_amount,
+            tick_array_start_index,
+            ctx.bumps.limit_order
+        )?;
+        emit_cpi!(LimitOrderPlaced{
+            pool : ctx.accounts.pool.key(),
+            order : ctx.accounts.limit_order.key(),
+            owner : ctx.accounts.payer.key(),
+            tick,
+            sell_token_0,
+            liquidity : liquidity_amount,
+            amount
+        });
+        Ok(())
+    }
+    pub fn claim_limit_order(ctx: Context<ClaimLimitOrder>)->Result<()>{
+        let (amount_0, amount_1, filled) = ctx.accounts.new()?;
+        emit_cpi!(LimitOrderClaimed{
+            pool : ctx.accounts.pool.key(),
+            order : ctx.accounts.limit_order.key(),
+            owner : ctx.accounts.payer.key(),
+            filled,
+            amount_0,
+            amount_1
+        });
+        Ok(())
+    }
+    pub fn set_settle_on_cross(ctx: Context<SetSettleOnCross>, settle_on_cross : u8)->Result<()>{
+        ctx.accounts.new(settle_on_cross)?;
+        Ok(())
+    }
+    pub fn settle_crossed_position(ctx: Context<SettleCrossedPosition>)->Result<()>{
+        let (liquidity, amount_0, amount_1, keeper_bounty) = ctx.accounts.new()?;
+        emit_cpi!(PositionSettled{
+            pool : ctx.accounts.pool.key(),
+            position : ctx.accounts.position.key(),
+            owner : ctx.accounts.owner.key(),
+            keeper : ctx.accounts.keeper.key(),
+            liquidity,
+            amount_0,
+            amount_1,
+            keeper_bounty
+        });
+        Ok(())
+    }
+    #[allow(clippy::too_many_arguments)]
+    pub fn zap_in<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ZapIn<'info>>,
+        amount_in : u64,
+        input_token_0 : bool,
+        lower_tick : i32,
+        uppar_tick : i32,
+        min_liquidity : u128,
+        tick_array_lower_start_index : i32,
+        tick_array_uppar_start_index : i32
+    )->Result<()>{
+        let result = ctx.accounts.new(
+            amount_in,
+            input_token_0,
+            lower_tick,
+            uppar_tick,
+            min_liquidity,
+            tick_array_lower_start_index,
+            tick_array_uppar_start_index,
+            ctx.bumps.position,
+            ctx.remaining_accounts
+        )?;
+        emit_cpi!(ZapInEvent{
+            pool : ctx.accounts.pool.key(),
+            position : ctx.accounts.position.key(),
+            owner : ctx.accounts.payer.key(),
+            input_token_0,
+            amount_in,
+            swap_amount_in : result.swap_amount_in,
+            swap_amount_out : result.swap_amount_out,
+            liquidity : result.liquidity,
+            dust_0 : result.dust_0,
+            dust_1 : result.dust_1
+        });
+        Ok(())
+    }
+    pub fn zap_out<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ZapOut<'info>>,
+        liquidity_amount : u128,
+        output_token_0 : bool,
+        amount_out_minimum : u64,
+        close_position : bool
+    )->Result<()>{
+        let result = ctx.accounts.new(
+            liquidity_amount,
+            output_token_0,
+            amount_out_minimum,
+            close_position,
+            ctx.remaining_accounts
+        )?;
+        emit_cpi!(ZapOutEvent{
+            pool : ctx.accounts.pool.key(),
+            position : ctx.accounts.position.key(),
+            owner : ctx.accounts.payer.key(),
+            output_token_0,
+            liquidity : liquidity_amount,
+            amount_0 : result.amount_0,
+            amount_1 : result.amount_1,
+            swap_amount_in : result.swap_amount_in,
+            amount_out : result.amount_out,
+            closed : close_position
+        });
         Ok(())
     }
 
@@ -0,0 +1,20 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(InitSpace)]
+pub struct GlobalConfig{
+    pub admin : Pubkey,
+    pub pending_admin : Pubkey, //proposed by the admin, only becomes admin once it accepts
+    pub bump : u8,
+    pub min_fee_rate : u32, //bounds of the dynamic fee, same unit as Pool.fee_rate
+    pub max_fee_rate : u32,
+    pub max_referral_fee_bps : u16, //most a front-end can take from a swap through a referral
+    pub keeper_bounty_bps : u16, //share of the withdrawn liquidity paid to whoever settles a crossed position
+}
+
+pub const DEFAULT_MIN_FEE_RATE : u32 = 500;
+pub const DEFAULT_MAX_FEE_RATE : u32 = 10_000;
+pub const DEFAULT_MAX_REFERRAL_FEE_BPS : u16 = 100;
+pub const DEFAULT_KEEPER_BOUNTY_BPS : u16 = 10;
+pub const MAX_KEEPER_BOUNTY_BPS : u16 = 1_000;
+pub const MAX_REFERRAL_FEE_BPS : u16 = 500;
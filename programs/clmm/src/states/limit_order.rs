@@ -0,0 +1,191 @@
+use anchor_lang::prelude::*;
+
+use crate::states::{Observation, Pool, TickArray, TickInfo};
+use crate::utils::{add_delta, ErrorCode};
+
+//liquidity on a single tick spacing [tick, tick + tick_spacing] on the side of the price that
+//only holds the token being sold, both ticks always sit in the same tick array
+#[account]
+#[derive(InitSpace)]
+pub struct LimitOrder{
+    pub owner : Pubkey,
+    pub pool : Pubkey,
+    pub tick : i32, //lower tick of the order
+    pub sell_token_0 : bool, //token 0 fills into token 1 going up, token 1 into token 0 going down
+    pub liquidity : u128,
+    pub fill_count : u32, //fill count of the filling tick when the order was placed
+    pub bump : u8
+}
+
+impl LimitOrder{
+    //the tick whose crossing fills the order, the upper one when selling token 0
+    pub fn fill_tick(&self, tick_spacing : i32)->i32{
+        if self.sell_token_0 {
+            self.tick + tick_spacing
+        } else {
+            self.tick
+        }
+    }
+
+    pub fn is_filled(&self, fill_tick_info : &TickInfo)->bool{
+        let fill_count = if self.sell_token_0 {
+            fill_tick_info.limit_order_fill_count_0
+        } else {
+            fill_tick_info.limit_order_fill_count_1
+        };
+        fill_count != self.fill_count
+    }
+}
+
+//adds or removes unfilled order liquidity, same bookkeeping as modify_position minus the fees,
+//orders do not earn swap fees, so their liquidity is also kept apart on the ticks and the pool
+pub fn modify_limit_order(
+    pool : &mut Pool,
+    order : &mut LimitOrder,
+    tick_array : &mut TickArray,
+    liquidity_delta : i128,
+    cumulatives : Option<&Observation>
+)->Result<()>{
+    let tick_lower = order.tick;
+    let tick_upper = order.tick + pool.tick_spacing;
+
+    let flipped_lower = tick_array
+        .get_tick_info_mutable(tick_lower, pool.tick_spacing)?
+        .update_liquidity(tick_lower, pool, liquidity_delta, true, cumulatives)?;
+    let flipped_upper = tick_array
+        .get_tick_info_mutable(tick_upper, pool.tick_spacing)?
+        .update_liquidity(tick_upper, pool, liquidity_delta, false, cumulatives)?;
+
+    let fill_tick_info = tick_array.get_tick_info_mutable(order.fill_tick(pool.tick_spacing), pool.tick_spacing)?;
+    if order.sell_token_0 {
+        fill_tick_info.limit_order_unfilled_0 = add_delta(fill_tick_info.limit_order_unfilled_0, liquidity_delta)?;
+    } else {
+        fill_tick_info.limit_order_unfilled_1 = add_delta(fill_tick_info.limit_order_unfilled_1, liquidity_delta)?;
+    }
+    order.liquidity = add_delta(order.liquidity, liquidity_delta)?;
+
+    let lower_info = tick_array.get_tick_info_mutable(tick_lower, pool.tick_spacing)?;
+    lower_info.limit_order_liquidity_net = lower_info.limit_order_liquidity_net
+        .checked_add(liquidity_delta)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    let upper_info = tick_array.get_tick_info_mutable(tick_upper, pool.tick_spacing)?;
+    upper_info.limit_order_liquidity_net = upper_info.limit_order_liquidity_net
+        .checked_sub(liquidity_delta)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    if liquidity_delta < 0 {
+        if flipped_lower {
+            tick_array.get_tick_info_mutable(tick_lower, pool.tick_spacing)?.clear();
+        }
+        if flipped_upper {
+            tick_array.get_tick_info_mutable(tick_upper, pool.tick_spacing)?.clear();
+        }
+    }
+
+    if tick_lower <= pool.current_tick && pool.current_tick < tick_upper {
+        pool.limit_order_liquidity = add_delta(pool.limit_order_liquidity, liquidity_delta)?;
+    }
+    pool.update_global_liquidity(tick_lower, tick_upper, liquidity_delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //5_000 of position liquidity active everywhere, the price just below the array at 0
+    fn pool()->Pool{
+        Pool{
+            tick_spacing : 60,
+            max_liquidity_per_tick : u128::MAX,
+            global_liquidity : 5_000,
+            current_tick : -1,
+            ..Pool::default()
+        }
+    }
+
+    fn tick_array()->TickArray{
+        TickArray{
+            pool : Pubkey::default(),
+            starting_tick : 0,
+            ticks : Default::default(),
+            bump : 0,
+            funder : Pubkey::default()
+        }
+    }
+
+    fn order(tick : i32, sell_token_0 : bool)->LimitOrder{
+        LimitOrder{
+            owner : Pubkey::default(),
+            pool : Pubkey::default(),
+            tick,
+            sell_token_0,
+            liquidity : 0,
+            fill_count : 0,
+            bump : 0
+        }
+    }
+
+    //moves the price the way swap_internal does, without the fee and price math
+    fn move_to(pool : &mut Pool, tick_array : &mut TickArray, to_tick : i32){
+        let (liquidity, limit_order_liquidity, _) = tick_array.cross_ticks(
+            pool,
+            to_tick,
+            to_tick < pool.current_tick,
+            pool.global_liquidity,
+            pool.limit_order_liquidity,
+            &Observation::default()
+        ).unwrap();
+        pool.global_liquidity = liquidity;
+        pool.limit_order_liquidity = limit_order_liquidity;
+        pool.current_tick = to_tick;
+    }
+
+    #[test]
+    fn unfilled_order_liquidity_does_not_earn_fees(){
+        let mut pool = pool();
+        let mut tick_array = tick_array();
+        let mut order = order(60, true);
+        let cumulatives = Observation::default();
+        modify_limit_order(&mut pool, &mut order, &mut tick_array, 1_000, Some(&cumulatives)).unwrap();
+        assert_eq!(pool.global_liquidity, 5_000);
+
+        //inside the order, it is active but the fees stay with the positions
+        move_to(&mut pool, &mut tick_array, 90);
+        assert_eq!(pool.global_liquidity, 6_000);
+        assert_eq!(pool.limit_order_liquidity, 1_000);
+        assert_eq!(pool.fee_liquidity(), 5_000);
+
+        //back below it before it filled
+        move_to(&mut pool, &mut tick_array, 30);
+        assert_eq!(pool.global_liquidity, 5_000);
+        assert_eq!(pool.limit_order_liquidity, 0);
+
+        //through the whole order, it fills and leaves both ticks
+        move_to(&mut pool, &mut tick_array, 150);
+        assert_eq!(pool.global_liquidity, 5_000);
+        assert_eq!(pool.limit_order_liquidity, 0);
+        let fill_tick_info = tick_array.get_tick_info(120, 60).unwrap();
+        assert!(order.is_filled(&fill_tick_info));
+        assert_eq!({ fill_tick_info.limit_order_filled_0 }, 1_000);
+        assert_eq!(fill_tick_info.initialized, 0);
+        assert_eq!(tick_array.get_tick_info(60, 60).unwrap().initialized, 0);
+    }
+
+    #[test]
+    fn cancelling_an_order_inside_its_range_takes_it_out_of_the_order_liquidity(){
+        let mut pool = Pool{ current_tick : 200, ..pool() };
+        let mut tick_array = tick_array();
+        let mut order = order(60, false);
+        let cumulatives = Observation::default();
+        modify_limit_order(&mut pool, &mut order, &mut tick_array, 1_000, Some(&cumulatives)).unwrap();
+
+        move_to(&mut pool, &mut tick_array, 100);
+        assert_eq!(pool.limit_order_liquidity, 1_000);
+        assert_eq!(pool.fee_liquidity(), 5_000);
+
+        modify_limit_order(&mut pool, &mut order, &mut tick_array, -1_000, None).unwrap();
+        assert_eq!(pool.global_liquidity, 5_000);
+        assert_eq!(pool.limit_order_liquidity, 0);
+        assert_eq!({ tick_array.get_tick_info(60, 60).unwrap().limit_order_liquidity_net }, 0);
+    }
+}
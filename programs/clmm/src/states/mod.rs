@@ -3,4 +3,10 @@ pub use pool::*;
 pub mod position;
 pub use position::*;
 pub mod tick_array;
-pub use tick_array::*;
\ No newline at end of file
+pub use tick_array::*;
+pub mod config;
+pub use config::*;
+pub mod observation;
+pub use observation::*;
+pub mod limit_order;
+pub use limit_order::*;
@@ -0,0 +1,352 @@
+use std::cell::{Ref, RefMut};
+use std::mem::size_of;
+
+use anchor_lang::prelude::*;
+
+use crate::utils::ErrorCode;
+
+//slots every pool starts with
+pub const OBSERVATION_NUM : usize = 100;
+//largest account the runtime allows
+const MAX_ACCOUNT_DATA_LENGTH : usize = 10 * 1024 * 1024;
+//the ring is never deserialized as a whole, so the account size is the only limit
+pub const MAX_OBSERVATION_CARDINALITY : usize =
+    (MAX_ACCOUNT_DATA_LENGTH - 8 - size_of::<ObservationState>()) / Observation::SPACE;
+
+#[zero_copy]
+#[repr(C, packed)]
+#[derive(Default)]
+pub struct Observation{
+    pub block_timestamp : u32,
+    pub tick_cumulative : i64, //sum of tick * seconds elapsed since the pool was created
+    pub seconds_per_liquidity_cumulative_x64 : u128, //sum of seconds / max(1, liquidity) as x64 fixed point
+    pub initialized : u8 //1 once the slot has been written
+}
+
+impl Observation{
+    pub const SPACE : usize = size_of::<Observation>();
+
+    //the observation as it would look at block_timestamp if tick and liquidity did not change in between
+    pub fn transform(&self, block_timestamp : u32, tick : i32, liquidity : u128)->Observation{
+        let delta = block_timestamp.wrapping_sub(self.block_timestamp);
+        Observation{
+            block_timestamp,
+            tick_cumulative : self.tick_cumulative.wrapping_add(tick as i64 * delta as i64),
+            seconds_per_liquidity_cumulative_x64 : self
+                .seconds_per_liquidity_cumulative_x64
+                .wrapping_add(((delta as u128) << 64) / liquidity.max(1)),
+            initialized : 1
+        }
+    }
+}
+
+//header of the observation account, the ring of observation_cardinality_next observations follows it
+//the ring is read and written in place, one slot at a time
+#[account(zero_copy)]
+#[repr(C, packed)]
+pub struct ObservationState{
+    pub pool : Pubkey,
+    pub bump : u8,
+    pub observation_index : u32, //slot of the most recent observation
+    pub observation_cardinality : u32, //slots the ring currently wraps around
+    pub observation_cardinality_next : u32 //slots allocated, the ring grows into them once it wraps
+}
+
+//what observe returns, cumulatives are in the same order as seconds_agos
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ObserveResult{
+    pub tick_cumulatives : Vec<i64>,
+    pub seconds_per_liquidity_cumulatives_x64 : Vec<u128>,
+    pub twap_tick : i32, //between the first and the last entry of seconds_agos
+    pub harmonic_mean_liquidity : u128
+}
+
+//the ring starts right after the header, every slot of the account data past it is an observation
+fn split_ring(data : &[u8])->(&ObservationState, &[Observation]){
+    let (header, ring) = data[8..].split_at(size_of::<ObservationState>());
+    let len = ring.len() / Observation::SPACE * Observation::SPACE;
+    (bytemuck::from_bytes(header), bytemuck::cast_slice(&ring[..len]))
+}
+
+fn split_ring_mut(data : &mut [u8])->(&mut ObservationState, &mut [Observation]){
+    let (header, ring) = data[8..].split_at_mut(size_of::<ObservationState>());
+    let len = ring.len() / Observation::SPACE * Observation::SPACE;
+    (bytemuck::from_bytes_mut(header), bytemuck::cast_slice_mut(&mut ring[..len]))
+}
+
+impl ObservationState{
+    pub fn space(cardinality : usize)->usize{
+        8 + size_of::<ObservationState>() + cardinality * Observation::SPACE
+    }
+
+    //the header and the ring of a freshly created account
+    pub fn load_ring_init<'a>(
+        loader : &'a AccountLoader<'_, ObservationState>
+    )->Result<(RefMut<'a, ObservationState>, RefMut<'a, [Observation]>)>{
+        drop(loader.load_init()?);
+        let data = loader.as_ref().try_borrow_mut_data()?;
+        Ok(RefMut::map_split(data, |data| split_ring_mut(data)))
+    }
+
+    pub fn load_ring_mut<'a>(
+        loader : &'a AccountLoader<'_, ObservationState>
+    )->Result<(RefMut<'a, ObservationState>, RefMut<'a, [Observation]>)>{
+        drop(loader.load_mut()?);
+        let data = loader.as_ref().try_borrow_mut_data()?;
+        Ok(RefMut::map_split(data, |data| split_ring_mut(data)))
+    }
+
+    pub fn load_ring<'a>(
+        loader : &'a AccountLoader<'_, ObservationState>
+    )->Result<(Ref<'a, ObservationState>, Ref<'a, [Observation]>)>{
+        drop(loader.load()?);
+        let data = loader.as_ref().try_borrow_data()?;
+        Ok(Ref::map_split(data, |data| split_ring(data)))
+    }
+
+    //writes the observation for block_timestamp and returns the cumulatives at that time,
+    //the ring is only borrowed for the duration of the call
+    pub fn record(
+        loader : &AccountLoader<'_, ObservationState>,
+        block_timestamp : u32,
+        tick : i32,
+        liquidity : u128
+    )->Result<Observation>{
+        let (mut observation_state, mut observations) = Self::load_ring_mut(loader)?;
+        observation_state.update(&mut observations, block_timestamp, tick, liquidity);
+        Ok(observation_state.snapshot(&observations, block_timestamp, tick, liquidity))
+    }
+
+    pub fn initialize(&mut self, observations : &mut [Observation], pool : Pubkey, bump : u8, block_timestamp : u32){
+        self.pool = pool;
+        self.bump = bump;
+        self.observation_index = 0;
+        self.observation_cardinality = OBSERVATION_NUM as u32;
+        self.observation_cardinality_next = OBSERVATION_NUM as u32;
+        observations[0] = Observation{
+            block_timestamp,
+            tick_cumulative : 0,
+            seconds_per_liquidity_cumulative_x64 : 0,
+            initialized : 1
+        };
+    }
+
+    //called with the tick and liquidity that were in effect up to now, before a swap moves them
+    //only the first call of every second writes, the rest of that second is already covered
+    pub fn update(&mut self, observations : &mut [Observation], block_timestamp : u32, tick : i32, liquidity : u128){
+        let last = observations[self.observation_index as usize];
+        if last.block_timestamp == block_timestamp {
+            return;
+        }
+        //slots added by increase_cardinality only join the ring once the write reaches the end of it
+        if self.observation_index + 1 == self.observation_cardinality
+            && self.observation_cardinality_next > self.observation_cardinality
+        {
+            self.observation_cardinality = self.observation_cardinality_next;
+        }
+        let next_index = (self.observation_index as usize + 1) % self.observation_cardinality as usize;
+        observations[next_index] = last.transform(block_timestamp, tick, liquidity);
+        self.observation_index = next_index as u32;
+    }
+
+    //cumulatives at block_timestamp, without writing anything
+    pub fn snapshot(&self, observations : &[Observation], block_timestamp : u32, tick : i32, liquidity : u128)->Observation{
+        observations[self.observation_index as usize].transform(block_timestamp, tick, liquidity)
+    }
+
+    //the account has to be reallocated to space(observation_cardinality_next) first,
+    //the new slots are zeroed and only written once the ring wraps into them
+    pub fn increase_cardinality(&mut self, observation_cardinality_next : u32)->Result<()>{
+        require!(
+            observation_cardinality_next > self.observation_cardinality_next
+                && observation_cardinality_next as usize <= MAX_OBSERVATION_CARDINALITY,
+            ErrorCode::InvalidObservationCardinality
+        );
+        self.observation_cardinality_next = observation_cardinality_next;
+        Ok(())
+    }
+
+    pub fn observe(
+        &self,
+        observations : &[Observation],
+        block_timestamp : u32,
+        seconds_agos : &[u32],
+        tick : i32,
+        liquidity : u128
+    )->Result<(Vec<i64>, Vec<u128>)>{
+        let mut tick_cumulatives = Vec::with_capacity(seconds_agos.len());
+        let mut seconds_per_liquidity_cumulatives_x64 = Vec::with_capacity(seconds_agos.len());
+        for seconds_ago in seconds_agos {
+            let observation = self.observe_single(observations, block_timestamp, *seconds_ago, tick, liquidity)?;
+            tick_cumulatives.push(observation.tick_cumulative);
+            seconds_per_liquidity_cumulatives_x64.push(observation.seconds_per_liquidity_cumulative_x64);
+        }
+        Ok((tick_cumulatives, seconds_per_liquidity_cumulatives_x64))
+    }
+
+    fn observe_single(
+        &self,
+        observations : &[Observation],
+        block_timestamp : u32,
+        seconds_ago : u32,
+        tick : i32,
+        liquidity : u128
+    )->Result<Observation>{
+        let target = block_timestamp
+            .checked_sub(seconds_ago)
+            .ok_or(ErrorCode::ObservationTooOld)?;
+        let last = observations[self.observation_index as usize];
+        if last.block_timestamp <= target {
+            //nothing was written since, extrapolate from the latest observation
+            return Ok(last.transform(target, tick, liquidity));
+        }
+
+        let (before_or_at, at_or_after) = self.get_surrounding_observations(observations, target)?;
+        if before_or_at.block_timestamp == target {
+            return Ok(before_or_at);
+        }
+        if at_or_after.block_timestamp == target {
+            return Ok(at_or_after);
+        }
+        //linear interpolation between the two observations around target
+        //cumulatives wrap like in uniswap, only differences between them mean anything
+        let observation_delta = at_or_after.block_timestamp.wrapping_sub(before_or_at.block_timestamp);
+        let target_delta = target.wrapping_sub(before_or_at.block_timestamp);
+        let tick_cumulative = before_or_at.tick_cumulative.wrapping_add(
+            (at_or_after.tick_cumulative.wrapping_sub(before_or_at.tick_cumulative) / observation_delta as i64)
+                .wrapping_mul(target_delta as i64)
+        );
+        //split into quotient and remainder so scaling by target_delta cannot overflow
+        let seconds_per_liquidity_delta = at_or_after
+            .seconds_per_liquidity_cumulative_x64
+            .wrapping_sub(before_or_at.seconds_per_liquidity_cumulative_x64);
+        let (observation_delta, target_delta) = (observation_delta as u128, target_delta as u128);
+        let seconds_per_liquidity_cumulative_x64 = before_or_at.seconds_per_liquidity_cumulative_x64.wrapping_add(
+            seconds_per_liquidity_delta / observation_delta * target_delta
+                + seconds_per_liquidity_delta % observation_delta * target_delta / observation_delta
+        );
+        Ok(Observation{
+            block_timestamp : target,
+            tick_cumulative,
+            seconds_per_liquidity_cumulative_x64,
+            initialized : 1
+        })
+    }
+
+    //binary search over the ring for the two observations around target
+    //target has to be older than the latest observation
+    fn get_surrounding_observations(&self, observations : &[Observation], target : u32)->Result<(Observation, Observation)>{
+        let index = self.observation_index as usize;
+        let cardinality = self.observation_cardinality as usize;
+        //the slot after the latest one is the oldest, unless the ring has not wrapped yet
+        let mut oldest_index = (index + 1) % cardinality;
+        if observations[oldest_index].initialized == 0 {
+            oldest_index = 0;
+        }
+        require!(
+            observations[oldest_index].block_timestamp <= target,
+            ErrorCode::ObservationTooOld
+        );
+
+        let len = if oldest_index == 0 { index + 1 } else { cardinality };
+        let mut low = 0;
+        let mut high = len - 1;
+        loop {
+            let mid = (low + high) / 2;
+            let before_or_at = observations[(oldest_index + mid) % cardinality];
+            let at_or_after = observations[(oldest_index + mid + 1) % cardinality];
+            if before_or_at.block_timestamp > target {
+                high = mid - 1;
+            } else if at_or_after.block_timestamp < target {
+                low = mid + 1;
+            } else {
+                return Ok((before_or_at, at_or_after));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(cardinality : usize)->(ObservationState, Vec<Observation>){
+        let mut observation_state = ObservationState{
+            pool : Pubkey::default(),
+            bump : 0,
+            observation_index : 0,
+            observation_cardinality : 0,
+            observation_cardinality_next : 0
+        };
+        let mut observations = vec![Observation::default(); cardinality];
+        observation_state.initialize(&mut observations, Pubkey::default(), 0, 1_000);
+        (observation_state, observations)
+    }
+
+    #[test]
+    fn update_writes_once_per_second() {
+        let (mut observation_state, mut observations) = ring(OBSERVATION_NUM);
+        observation_state.update(&mut observations, 1_010, 5, 1);
+        observation_state.update(&mut observations, 1_010, 7, 1);
+        assert_eq!({ observation_state.observation_index }, 1);
+        assert_eq!({ observations[1].tick_cumulative }, 50);
+    }
+
+    #[test]
+    fn grown_slots_join_the_ring_once_it_wraps() {
+        let (mut observation_state, mut observations) = ring(OBSERVATION_NUM + 50);
+        observation_state.observation_cardinality_next = (OBSERVATION_NUM + 50) as u32;
+        for second in 1..OBSERVATION_NUM as u32 {
+            observation_state.update(&mut observations, 1_000 + second, 0, 1);
+        }
+        assert_eq!({ observation_state.observation_index }, OBSERVATION_NUM as u32 - 1);
+        assert_eq!({ observation_state.observation_cardinality }, OBSERVATION_NUM as u32);
+
+        observation_state.update(&mut observations, 1_000 + OBSERVATION_NUM as u32, 0, 1);
+        assert_eq!({ observation_state.observation_index }, OBSERVATION_NUM as u32);
+        assert_eq!({ observation_state.observation_cardinality }, (OBSERVATION_NUM + 50) as u32);
+    }
+
+    #[test]
+    fn increase_cardinality_is_capped_by_the_account_size() {
+        let (mut observation_state, _) = ring(OBSERVATION_NUM);
+        //a 24h window at one observation a second
+        assert!(observation_state.increase_cardinality(86_400).is_ok());
+        assert!(observation_state.increase_cardinality(MAX_OBSERVATION_CARDINALITY as u32).is_ok());
+        assert!(observation_state.increase_cardinality(MAX_OBSERVATION_CARDINALITY as u32 + 1).is_err());
+        assert!(ObservationState::space(MAX_OBSERVATION_CARDINALITY) <= MAX_ACCOUNT_DATA_LENGTH);
+    }
+
+    #[test]
+    fn observe_interpolates_between_observations() {
+        let (mut observation_state, mut observations) = ring(OBSERVATION_NUM);
+        observation_state.update(&mut observations, 1_010, 10, 1);
+        observation_state.update(&mut observations, 1_020, 20, 1);
+        let (tick_cumulatives, _) = observation_state
+            .observe(&observations, 1_020, &[15, 5], 30, 1)
+            .unwrap();
+        //tick 10 for the first 10 seconds, then 20 for the next 10
+        assert_eq!(tick_cumulatives, vec![50, 200]);
+    }
+
+    #[test]
+    fn observe_interpolates_across_wrapped_cumulatives() {
+        let (mut observation_state, mut observations) = ring(OBSERVATION_NUM);
+        //both cumulatives sit right below their wrap point
+        observations[0].tick_cumulative = i64::MAX - 50;
+        observations[0].seconds_per_liquidity_cumulative_x64 = u128::MAX - (5 << 64);
+        observation_state.update(&mut observations, 1_010, 10, 1);
+        observation_state.update(&mut observations, 1_020, 20, 1);
+        let (tick_cumulatives, seconds_per_liquidity_cumulatives_x64) = observation_state
+            .observe(&observations, 1_020, &[15], 30, 1)
+            .unwrap();
+        assert_eq!(tick_cumulatives, vec![(i64::MAX - 50).wrapping_add(50)]);
+        assert_eq!(seconds_per_liquidity_cumulatives_x64, vec![(u128::MAX - (5 << 64)).wrapping_add(5 << 64)]);
+
+        let (tick_cumulatives, seconds_per_liquidity_cumulatives_x64) = observation_state
+            .observe(&observations, 1_020, &[5], 30, 1)
+            .unwrap();
+        assert_eq!(tick_cumulatives, vec![(i64::MAX - 50).wrapping_add(200)]);
+        assert_eq!(seconds_per_liquidity_cumulatives_x64, vec![(u128::MAX - (5 << 64)).wrapping_add(15 << 64)]);
+    }
+}
@@ -1,17 +1,234 @@
 use anchor_lang::prelude::*;
 
+use crate::utils::{add_delta, ErrorCode};
+
 #[account]
-#[derive(InitSpace)]
+#[derive(InitSpace, Default)]
 pub struct Pool{
     pub token_mint_0 : Pubkey,
     pub token_mint_1 : Pubkey,
     pub token_vault_0 : Pubkey,
     pub token_vault_1 : Pubkey,
     pub global_liquidity : u128, //Amount of liquidity currently active at the current price its not total liq its liq that is usable right now
+    pub limit_order_liquidity : u128, //unfilled limit order liquidity inside global_liquidity, it earns no fees
     pub sqrt_price_x96 : u128,  //price = token1/token0  but we use in squre root casue of precision ,swap math become linear,prevent overflow
     //x96 means we use fixed point with 96 bits for fractional part (Solana has no floating points.)
     pub current_tick : i32, //current tick of the pool , tick is used to represent price in discrete steps
     pub tick_spacing : i32,
+    pub tick_spacing_seed : [u8; 4], //tick_spacing.to_le_bytes(), kept around so signer seeds can borrow it
+    pub max_liquidity_per_tick : u128, //derived from tick_spacing at init, no tick can hold more than this
     pub bump : u8,
+    pub fee_rate : u32, //hundredths of a bip taken from every swap input
+    pub fee_growth_global_0_x64 : u128, //fees earned per unit of liquidity over the whole life of the pool
+    pub fee_growth_global_1_x64 : u128,
+    pub status : u8, //bit set of paused operations, see POOL_STATUS_*
+    pub unlocked : bool, //false while an instruction is operating on the pool, see lock
+    pub dynamic_fee : bool, //fee_rate follows volatility_accumulator between the config fee bounds
+    pub volatility_accumulator : u32, //initialized ticks recent swaps crossed, decays with time between swaps
+    pub last_swap_timestamp : u32,
 }
+
+pub const DEFAULT_FEE_RATE : u32 = 1_000;
+
+pub const POOL_STATUS_SWAP_PAUSED : u8 = 1 << 0;
+pub const POOL_STATUS_DEPOSIT_PAUSED : u8 = 1 << 1;
+pub const POOL_STATUS_WITHDRAW_PAUSED : u8 = 1 << 2;
+pub const POOL_STATUS_COLLECT_FEE_PAUSED : u8 = 1 << 3;
+pub const POOL_STATUS_FLASH_PAUSED : u8 = 1 << 4;
+//swaps closer together than this keep building on the same accumulator
+pub const VOLATILITY_FILTER_PERIOD : u32 = 30;
+//after this long without a swap the pool is considered calm again
+pub const VOLATILITY_DECAY_PERIOD : u32 = 600;
+//share of the accumulator kept between the two periods, in bps
+pub const VOLATILITY_REDUCTION_FACTOR : u32 = 5_000;
+pub const MAX_VOLATILITY_ACCUMULATOR : u32 = 100_000;
+//fee added on top of the config min_fee_rate for every crossed tick in the accumulator
+pub const FEE_RATE_PER_VOLATILITY : u32 = 50;
 //A tick is an integer index representing a price range
+
+impl Pool{
+    pub fn is_paused(&self, status_bit : u8)->bool{
+        self.status & status_bit != 0
+    }
+
+    //taken by every instruction that moves the pool. a flash callback can not get here today,
+    //the runtime rejects a CPI back into clmm while clmm is on the stack before any of its code
+    //runs, so re-entry fails with the runtime's reentrancy error and never with PoolLocked.
+    //the lock stays for the one re-entry the runtime does allow, clmm invoking itself, and in
+    //case that rule ever loosens, it only needs to be written out before handing control to
+    //another program
+    pub fn lock(&mut self)->Result<()>{
+        require!(self.unlocked, ErrorCode::PoolLocked);
+        self.unlocked = false;
+        Ok(())
+    }
+
+    pub fn unlock(&mut self){
+        self.unlocked = true;
+    }
+
+    //decays the accumulator by the time since the last swap and sets the fee rate the next swap pays
+    //the bounds come from the config on every swap so set_fee_bounds reaches pools already running
+    pub fn update_dynamic_fee(&mut self, block_timestamp : u32, min_fee_rate : u32, max_fee_rate : u32){
+        if !self.dynamic_fee {
+            return;
+        }
+        let elapsed = block_timestamp.saturating_sub(self.last_swap_timestamp);
+        if elapsed >= VOLATILITY_DECAY_PERIOD {
+            self.volatility_accumulator = 0;
+        } else if elapsed >= VOLATILITY_FILTER_PERIOD {
+            self.volatility_accumulator = (self.volatility_accumulator as u64
+                * VOLATILITY_REDUCTION_FACTOR as u64
+                / 10_000) as u32;
+        }
+        let variable_fee_rate = self.volatility_accumulator.saturating_mul(FEE_RATE_PER_VOLATILITY);
+        self.fee_rate = min_fee_rate
+            .saturating_add(variable_fee_rate)
+            .min(max_fee_rate);
+    }
+
+    //called once the swap is done with the initialized ticks it crossed, empty tick spacings
+    //the price went over do not count
+    pub fn accumulate_volatility(&mut self, block_timestamp : u32, ticks_crossed : u32){
+        if !self.dynamic_fee {
+            return;
+        }
+        self.volatility_accumulator = self.volatility_accumulator
+            .saturating_add(ticks_crossed)
+            .min(MAX_VOLATILITY_ACCUMULATOR);
+        self.last_swap_timestamp = block_timestamp;
+    }
+
+    //seeds of the pool PDA, the pool is the authority of both vaults
+    pub fn signer_seeds(&self)->[&[u8]; 5]{
+        [
+            b"pool".as_ref(),
+            self.token_mint_0.as_ref(),
+            self.token_mint_1.as_ref(),
+            self.tick_spacing_seed.as_ref(),
+            std::slice::from_ref(&self.bump),
+        ]
+    }
+
+    //liquidity the swap fees are shared by, active limit orders are left out
+    pub fn fee_liquidity(&self)->u128{
+        self.global_liquidity.saturating_sub(self.limit_order_liquidity)
+    }
+
+    //global_liquidity only tracks liquidity whose range contains the current tick
+    pub fn update_global_liquidity(
+        &mut self,
+        tick_lower : i32,
+        tick_upper : i32,
+        liquidity_delta : i128
+    )->Result<()>{
+        if tick_lower <= self.current_tick && self.current_tick < tick_upper {
+            self.global_liquidity = add_delta(self.global_liquidity, liquidity_delta)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlocked_pool()->Pool{
+        Pool{ unlocked : true, ..Pool::default() }
+    }
+
+    //a dynamic fee pool that last swapped at t=1000 with 40 crossed ticks in the accumulator
+    fn volatile_pool()->Pool{
+        Pool{
+            tick_spacing : 60,
+            dynamic_fee : true,
+            volatility_accumulator : 40,
+            last_swap_timestamp : 1_000,
+            ..Pool::default()
+        }
+    }
+
+    #[test]
+    fn lock_rejects_a_second_lock(){
+        let mut pool = unlocked_pool();
+        pool.lock().unwrap();
+        assert!(!pool.unlocked);
+        assert_eq!(pool.lock().unwrap_err(), ErrorCode::PoolLocked.into());
+    }
+
+    #[test]
+    fn unlock_allows_locking_again(){
+        let mut pool = unlocked_pool();
+        pool.lock().unwrap();
+        pool.unlock();
+        assert!(pool.unlocked);
+        pool.lock().unwrap();
+    }
+
+    #[test]
+    fn a_pool_that_was_never_unlocked_is_locked(){
+        let mut pool = Pool::default();
+        assert_eq!(pool.lock().unwrap_err(), ErrorCode::PoolLocked.into());
+    }
+
+    #[test]
+    fn accumulator_is_kept_within_the_filter_period(){
+        let mut pool = volatile_pool();
+        pool.update_dynamic_fee(1_000 + VOLATILITY_FILTER_PERIOD - 1, 300, 5_000);
+        assert_eq!(pool.volatility_accumulator, 40);
+        assert_eq!(pool.fee_rate, 300 + 40 * FEE_RATE_PER_VOLATILITY);
+    }
+
+    #[test]
+    fn accumulator_is_reduced_after_the_filter_period(){
+        let mut pool = volatile_pool();
+        pool.update_dynamic_fee(1_000 + VOLATILITY_FILTER_PERIOD, 300, 5_000);
+        assert_eq!(pool.volatility_accumulator, 20);
+        assert_eq!(pool.fee_rate, 300 + 20 * FEE_RATE_PER_VOLATILITY);
+
+        let mut pool = volatile_pool();
+        pool.update_dynamic_fee(1_000 + VOLATILITY_DECAY_PERIOD - 1, 300, 5_000);
+        assert_eq!(pool.volatility_accumulator, 20);
+    }
+
+    #[test]
+    fn accumulator_is_cleared_after_the_decay_period(){
+        let mut pool = volatile_pool();
+        pool.update_dynamic_fee(1_000 + VOLATILITY_DECAY_PERIOD, 300, 5_000);
+        assert_eq!(pool.volatility_accumulator, 0);
+        assert_eq!(pool.fee_rate, 300);
+    }
+
+    #[test]
+    fn fee_rate_stays_within_the_bounds_it_is_given(){
+        let mut pool = volatile_pool();
+        pool.update_dynamic_fee(1_000, 300, 1_000);
+        assert_eq!(pool.fee_rate, 1_000);
+        //new bounds apply on the next update, without turning the dynamic fee off and on
+        pool.update_dynamic_fee(1_000, 2_500, 5_000);
+        assert_eq!(pool.fee_rate, 2_500 + 40 * FEE_RATE_PER_VOLATILITY);
+    }
+
+    #[test]
+    fn fixed_fee_pool_ignores_the_accumulator(){
+        let mut pool = Pool{ dynamic_fee : false, fee_rate : DEFAULT_FEE_RATE, ..volatile_pool() };
+        pool.update_dynamic_fee(1_000, 300, 5_000);
+        assert_eq!(pool.fee_rate, DEFAULT_FEE_RATE);
+        pool.accumulate_volatility(1_010, 10);
+        assert_eq!(pool.volatility_accumulator, 40);
+    }
+
+    #[test]
+    fn swaps_add_the_ticks_they_crossed(){
+        let mut pool = volatile_pool();
+        //the price may have moved over any number of empty spacings, only the crossings count
+        pool.current_tick = -1_000;
+        pool.accumulate_volatility(1_010, 3);
+        assert_eq!(pool.volatility_accumulator, 40 + 3);
+        assert_eq!(pool.last_swap_timestamp, 1_010);
+
+        pool.volatility_accumulator = MAX_VOLATILITY_ACCUMULATOR - 1;
+        pool.accumulate_volatility(1_020, 2);
+        assert_eq!(pool.volatility_accumulator, MAX_VOLATILITY_ACCUMULATOR);
+    }
+}
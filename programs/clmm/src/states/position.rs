@@ -1,5 +1,8 @@
 use anchor_lang::prelude::*;
 
+use crate::states::{get_fee_growth_inside, Observation, Pool, TickArray};
+use crate::utils::{add_delta, mul_shr_64, ErrorCode};
+
 #[account]
 #[derive(InitSpace)]
 pub struct Position{
@@ -8,5 +11,102 @@ pub struct Position{
     pub tick_uppar : i32,
     pub owner : Pubkey,
     pub pool : Pubkey,
-    pub bump : u8
-}
\ No newline at end of file
+    pub bump : u8,
+    pub fee_growth_inside_0_last_x64 : u128, //fee growth inside the range the last time the position was touched
+    pub fee_growth_inside_1_last_x64 : u128,
+    pub tokens_owed_0 : u64, //fees earned but not collected yet
+    pub tokens_owed_1 : u64,
+    pub settle_on_cross : u8 //POSITION_SETTLE_*, lets any keeper withdraw the position once the price is past it
+}
+
+pub const POSITION_SETTLE_NONE : u8 = 0;
+//the price is at or above tick_upper, the position is all token 1
+pub const POSITION_SETTLE_ABOVE : u8 = 1;
+//the price is below tick_lower, the position is all token 0
+pub const POSITION_SETTLE_BELOW : u8 = 2;
+
+impl Position{
+    //credits the fees earned since the last update, then applies the liquidity change
+    pub fn update(
+        &mut self,
+        liquidity_delta : i128,
+        fee_growth_inside_0_x64 : u128,
+        fee_growth_inside_1_x64 : u128
+    )->Result<()>{
+        let owed_0 = mul_shr_64(
+            fee_growth_inside_0_x64.wrapping_sub(self.fee_growth_inside_0_last_x64),
+            self.liquidity
+        )?;
+        let owed_1 = mul_shr_64(
+            fee_growth_inside_1_x64.wrapping_sub(self.fee_growth_inside_1_last_x64),
+            self.liquidity
+        )?;
+        //fees past u64::MAX are lost, same as uniswap, the owner has to collect before that
+        self.tokens_owed_0 = self.tokens_owed_0.saturating_add(u64::try_from(owed_0).unwrap_or(u64::MAX));
+        self.tokens_owed_1 = self.tokens_owed_1.saturating_add(u64::try_from(owed_1).unwrap_or(u64::MAX));
+
+        self.liquidity = add_delta(self.liquidity, liquidity_delta)?;
+        self.fee_growth_inside_0_last_x64 = fee_growth_inside_0_x64;
+        self.fee_growth_inside_1_last_x64 = fee_growth_inside_1_x64;
+        Ok(())
+    }
+}
+
+//updates both ticks, the position and the pool for a liquidity change
+//a zero delta only settles the fees the position has earned so far
+//cumulatives are only needed when liquidity is added, that is the only way a tick gets initialized
+//upper_tick_array is None when both ticks sit in lower_tick_array
+pub fn modify_position(
+    pool : &mut Pool,
+    position : &mut Position,
+    lower_tick_array : &mut TickArray,
+    upper_tick_array : Option<&mut TickArray>,
+    liquidity_delta : i128,
+    cumulatives : Option<&Observation>
+)->Result<()>{
+    let tick_lower = position.tick_lower;
+    let tick_upper = position.tick_uppar;
+    require!(
+        liquidity_delta != 0 || position.liquidity > 0,
+        ErrorCode::NoLiquidityToRemove
+    );
+
+    //both ticks are worked on as copies and written back at the end, the arrays may be one account
+    let mut lower_tick_info = lower_tick_array.get_tick_info(tick_lower, pool.tick_spacing)?;
+    let mut upper_tick_info = match &upper_tick_array {
+        Some(upper_tick_array) => upper_tick_array.get_tick_info(tick_upper, pool.tick_spacing)?,
+        None => lower_tick_array.get_tick_info(tick_upper, pool.tick_spacing)?
+    };
+
+    let mut flipped_lower = false;
+    let mut flipped_upper = false;
+    if liquidity_delta != 0 {
+        flipped_lower = lower_tick_info.update_liquidity(tick_lower, pool, liquidity_delta, true, cumulatives)?;
+        flipped_upper = upper_tick_info.update_liquidity(tick_upper, pool, liquidity_delta, false, cumulatives)?;
+    }
+
+    let (fee_growth_inside_0_x64, fee_growth_inside_1_x64) = get_fee_growth_inside(
+        &lower_tick_info,
+        &upper_tick_info,
+        tick_lower,
+        tick_upper,
+        pool
+    );
+    position.update(liquidity_delta, fee_growth_inside_0_x64, fee_growth_inside_1_x64)?;
+
+    //ticks nobody references anymore go back to their empty state
+    if liquidity_delta < 0 {
+        if flipped_lower {
+            lower_tick_info.clear();
+        }
+        if flipped_upper {
+            upper_tick_info.clear();
+        }
+    }
+
+    *lower_tick_array.get_tick_info_mutable(tick_lower, pool.tick_spacing)? = lower_tick_info;
+    let upper_tick_array = upper_tick_array.unwrap_or(lower_tick_array);
+    *upper_tick_array.get_tick_info_mutable(tick_upper, pool.tick_spacing)? = upper_tick_info;
+
+    pool.update_global_liquidity(tick_lower, tick_upper, liquidity_delta)
+}
@@ -1,26 +1,68 @@
+use std::cell::RefMut;
+use std::mem::size_of;
+
 use anchor_lang::prelude::*;
 
-use crate::utils::ErrorCode;
+use crate::states::{Observation, Pool};
+use crate::utils::{add_delta, ErrorCode};
 
 
 pub const TICKS_PER_ARRAY :usize = 30;
-#[account]
+#[zero_copy]
+#[repr(C, packed)]
+#[derive(Default)]
 pub struct TickInfo{
-    pub initialized : bool,
+    pub initialized : u8, //1 while any liquidity references the tick
     pub liquidity_gross : u128,  //Sum of absolute liquidity amounts that reference this tick
-    pub liquidity_net : i128     //Net change in active liquidity when price crosses this tick upward
+    pub liquidity_net : i128,    //Net change in active liquidity when price crosses this tick upward
+    pub fee_growth_outside_0_x64 : u128, //fee growth on the other side of this tick from the current price
+    pub fee_growth_outside_1_x64 : u128,
+    pub seconds_per_liquidity_outside_x64 : u128, //same idea as the fee growth, for the oracle cumulatives
+    pub seconds_outside : u32,
+    pub tick_cumulative_outside : i64,
+    //limit orders selling token 0 end at this tick and fill when the price crosses it upward,
+    //orders selling token 1 start at it and fill when the price crosses it downward
+    pub limit_order_unfilled_0 : u128,
+    pub limit_order_unfilled_1 : u128,
+    pub limit_order_liquidity_net : i128, //part of liquidity_net that belongs to unfilled limit orders
+    pub limit_order_filled_0 : u128, //filled but not claimed yet, kept even when the tick is cleared
+    pub limit_order_filled_1 : u128,
+    pub limit_order_fill_count_0 : u32, //bumped on every fill, an order placed before the bump was filled
+    pub limit_order_fill_count_1 : u32
 }
 impl TickInfo{
-    pub const SPACE :usize = 8 + 16 + 16 + 1;
-    pub fn update_liquidity(&mut self,liquidity_delta:i128,is_lower : bool)->Result<()>{
+    pub const SPACE :usize = size_of::<TickInfo>();
+    //returns true when the tick flipped from uninitialized to initialized or the other way round
+    //a tick that flipped back to zero has to be cleared by the caller once it is done reading it
+    pub fn update_liquidity(
+        &mut self,
+        tick : i32,
+        pool : &Pool,
+        liquidity_delta:i128,
+        is_lower : bool,
+        cumulatives : Option<&Observation> //oracle values right now, only read when the tick gets initialized
+    )->Result<bool>{
+        let liquidity_gross_before = self.liquidity_gross;
+        let liquidity_gross_after = add_delta(liquidity_gross_before, liquidity_delta)?;
+        require!(
+            liquidity_gross_after <= pool.max_liquidity_per_tick,
+            ErrorCode::LiquidityPerTickExceeded
+        );
+        let flipped = (liquidity_gross_after == 0) != (liquidity_gross_before == 0);
 
-        if !self.initialized {
-            self.initialized = true;
+        if liquidity_gross_before == 0 {
+            //by convention all growth before a tick is initialized happened below it
+            if tick <= pool.current_tick {
+                self.fee_growth_outside_0_x64 = pool.fee_growth_global_0_x64;
+                self.fee_growth_outside_1_x64 = pool.fee_growth_global_1_x64;
+                let cumulatives = cumulatives.ok_or(ErrorCode::MissingObservation)?;
+                self.seconds_per_liquidity_outside_x64 = cumulatives.seconds_per_liquidity_cumulative_x64;
+                self.seconds_outside = cumulatives.block_timestamp;
+                self.tick_cumulative_outside = cumulatives.tick_cumulative;
+            }
+            self.initialized = 1;
         }
-        self.liquidity_gross = self
-            .liquidity_gross
-            .checked_add(liquidity_delta.unsigned_abs())
-            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.liquidity_gross = liquidity_gross_after;
         
         if is_lower {
             self.liquidity_net = self
@@ -33,32 +75,197 @@ impl TickInfo{
                 .checked_sub(liquidity_delta)
                 .ok_or(ErrorCode::ArithmeticOverflow)?;
         }
+        Ok(flipped)
+    }
+
+    //price moved across this tick, everything outside is now on the other side
+    pub fn cross(&mut self, pool : &Pool, cumulatives : &Observation){
+        self.fee_growth_outside_0_x64 = pool
+            .fee_growth_global_0_x64
+            .wrapping_sub(self.fee_growth_outside_0_x64);
+        self.fee_growth_outside_1_x64 = pool
+            .fee_growth_global_1_x64
+            .wrapping_sub(self.fee_growth_outside_1_x64);
+        self.seconds_per_liquidity_outside_x64 = cumulatives
+            .seconds_per_liquidity_cumulative_x64
+            .wrapping_sub(self.seconds_per_liquidity_outside_x64);
+        self.seconds_outside = cumulatives.block_timestamp.wrapping_sub(self.seconds_outside);
+        self.tick_cumulative_outside = cumulatives
+            .tick_cumulative
+            .wrapping_sub(self.tick_cumulative_outside);
+    }
+
+    //filled orders still have to be claimed against this tick after its liquidity is gone
+    pub fn clear(&mut self){
+        *self = TickInfo{
+            limit_order_filled_0 : self.limit_order_filled_0,
+            limit_order_filled_1 : self.limit_order_filled_1,
+            limit_order_fill_count_0 : self.limit_order_fill_count_0,
+            limit_order_fill_count_1 : self.limit_order_fill_count_1,
+            ..TickInfo::default()
+        };
+    }
+
+    //gross/net bookkeeping of filled limit orders leaving the tick
+    fn remove_limit_order_liquidity(&mut self, liquidity : u128, is_lower : bool)->Result<()>{
+        self.liquidity_gross = self.liquidity_gross
+            .checked_sub(liquidity)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let liquidity = if is_lower { -(liquidity as i128) } else { liquidity as i128 };
+        self.liquidity_net = self.liquidity_net
+            .checked_add(liquidity)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.limit_order_liquidity_net = self.limit_order_liquidity_net
+            .checked_add(liquidity)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        if self.liquidity_gross == 0 {
+            self.clear();
+        }
         Ok(())
     }
 //  lower → +liquidity
 //  upper → −liquidity
 }
 
+//fees earned per unit of liquidity between tick_lower and tick_upper
+pub fn get_fee_growth_inside(
+    tick_lower_info : &TickInfo,
+    tick_upper_info : &TickInfo,
+    tick_lower : i32,
+    tick_upper : i32,
+    pool : &Pool
+)->(u128, u128){
+    let (below_0, below_1) = if pool.current_tick >= tick_lower {
+        (tick_lower_info.fee_growth_outside_0_x64, tick_lower_info.fee_growth_outside_1_x64)
+    } else {
+        (
+            pool.fee_growth_global_0_x64.wrapping_sub(tick_lower_info.fee_growth_outside_0_x64),
+            pool.fee_growth_global_1_x64.wrapping_sub(tick_lower_info.fee_growth_outside_1_x64)
+        )
+    };
+    let (above_0, above_1) = if pool.current_tick < tick_upper {
+        (tick_upper_info.fee_growth_outside_0_x64, tick_upper_info.fee_growth_outside_1_x64)
+    } else {
+        (
+            pool.fee_growth_global_0_x64.wrapping_sub(tick_upper_info.fee_growth_outside_0_x64),
+            pool.fee_growth_global_1_x64.wrapping_sub(tick_upper_info.fee_growth_outside_1_x64)
+        )
+    };
+    (
+        pool.fee_growth_global_0_x64.wrapping_sub(below_0).wrapping_sub(above_0),
+        pool.fee_growth_global_1_x64.wrapping_sub(below_1).wrapping_sub(above_1)
+    )
+}
 
-#[account]
+
+//tick cumulative, seconds per liquidity and seconds spent while the price was between tick_lower and tick_upper
+//only differences between two snapshots of the same range mean anything
+pub fn get_cumulatives_inside(
+    tick_lower_info : &TickInfo,
+    tick_upper_info : &TickInfo,
+    tick_lower : i32,
+    tick_upper : i32,
+    current_tick : i32,
+    cumulatives : &Observation
+)->(i64, u128, u32){
+    if current_tick < tick_lower {
+        (
+            tick_lower_info.tick_cumulative_outside.wrapping_sub(tick_upper_info.tick_cumulative_outside),
+            tick_lower_info.seconds_per_liquidity_outside_x64.wrapping_sub(tick_upper_info.seconds_per_liquidity_outside_x64),
+            tick_lower_info.seconds_outside.wrapping_sub(tick_upper_info.seconds_outside)
+        )
+    } else if current_tick < tick_upper {
+        (
+            cumulatives
+                .tick_cumulative
+                .wrapping_sub(tick_lower_info.tick_cumulative_outside)
+                .wrapping_sub(tick_upper_info.tick_cumulative_outside),
+            cumulatives
+                .seconds_per_liquidity_cumulative_x64
+                .wrapping_sub(tick_lower_info.seconds_per_liquidity_outside_x64)
+                .wrapping_sub(tick_upper_info.seconds_per_liquidity_outside_x64),
+            cumulatives
+                .block_timestamp
+                .wrapping_sub(tick_lower_info.seconds_outside)
+                .wrapping_sub(tick_upper_info.seconds_outside)
+        )
+    } else {
+        (
+            tick_upper_info.tick_cumulative_outside.wrapping_sub(tick_lower_info.tick_cumulative_outside),
+            tick_upper_info.seconds_per_liquidity_outside_x64.wrapping_sub(tick_lower_info.seconds_per_liquidity_outside_x64),
+            tick_upper_info.seconds_outside.wrapping_sub(tick_lower_info.seconds_outside)
+        )
+    }
+}
+
+//read and written in place, a borsh copy of the ticks would not fit on the stack
+#[account(zero_copy)]
+#[repr(C, packed)]
 pub struct TickArray{
     pub pool : Pubkey,
     pub starting_tick : i32,
     pub ticks : [TickInfo;TICKS_PER_ARRAY],
-    pub bump : u8
+    pub bump : u8,
+    pub funder : Pubkey  //whoever paid the rent when the array was created, gets it back on close
 }
 
 impl TickArray {
-    pub const SPACE : usize = 8 + 32 + 4 + TICKS_PER_ARRAY * 48 + 1;
+    pub const SPACE : usize = 8 + size_of::<TickArray>();
+
+    //init_if_needed hands over a freshly created array without its discriminator,
+    //that one has to go through load_init, an existing one through load_mut
+    pub fn load_or_init<'a>(loader : &'a AccountLoader<'_, TickArray>)->Result<RefMut<'a, TickArray>>{
+        let created = loader.as_ref().try_borrow_data()?[..8].iter().all(|byte| *byte == 0);
+        if created {
+            loader.load_init()
+        } else {
+            loader.load_mut()
+        }
+    }
+
+    //the arrays of both ticks of a range, a range inside a single array only borrows it once
+    //and gets None for the upper one
+    pub fn load_range_mut<'a>(
+        lower : &'a AccountLoader<'_, TickArray>,
+        upper : &'a AccountLoader<'_, TickArray>
+    )->Result<(RefMut<'a, TickArray>, Option<RefMut<'a, TickArray>>)>{
+        let lower_tick_array = Self::load_or_init(lower)?;
+        if lower.key() == upper.key() {
+            return Ok((lower_tick_array, None));
+        }
+        Ok((lower_tick_array, Some(Self::load_or_init(upper)?)))
+    }
+
+    //a heap copy to run a quote on, the account itself is left untouched
+    pub fn boxed_copy(&self)->Box<TickArray>{
+        let mut copy : Box<TickArray> = bytemuck::zeroed_box();
+        bytemuck::bytes_of_mut(copy.as_mut()).copy_from_slice(bytemuck::bytes_of(self));
+        copy
+    }
+
+    //no tick inside the array is referenced by any position or unclaimed limit order anymore
+    pub fn is_empty(&self)->bool{
+        self.ticks.iter().all(|tick| {
+            tick.liquidity_gross == 0
+                && tick.limit_order_filled_0 == 0
+                && tick.limit_order_filled_1 == 0
+        })
+    }
+
+    //how many ticks a single array covers
+    pub fn span(tick_spacing : i32)->i32{
+        TICKS_PER_ARRAY as i32 * tick_spacing
+    }
 
     //“Given a tick value, what should be the starting_tick of the tick array
+    //rounds towards negative infinity so tick -1 lands in the array before 0
     pub fn get_starting_tick_index(tick : i32 , tick_spacing:i32)->i32{ 
         let tick_per_array_i32 = TICKS_PER_ARRAY as i32;
 
         let array_idx = tick 
-            .checked_div(tick_spacing)
+            .checked_div_euclid(tick_spacing)
             .expect("Div by zero:tick_spacing")
-            .checked_div(tick_per_array_i32)
+            .checked_div_euclid(tick_per_array_i32)
             .expect("Div by zero: TICKS_PER_ARRAY");
         array_idx
             .checked_mul(tick_per_array_i32)
@@ -67,20 +274,211 @@ impl TickArray {
             .expect("Mul overflow")
     }
     //“Inside this tick array, which TickInfo corresponds to the given tick?
-    pub fn get_tick_info_mutable(&mut self, tick: i32, tick_spacing: i32) -> Result<&mut TickInfo> {
-        let ticks_per_array_i32 = TICKS_PER_ARRAY as i32;
-        let offset = (tick
+    fn tick_offset(&self, tick: i32, tick_spacing: i32) -> Result<usize> {
+        //swaps only ever visit the canonical array of a tick, a tick stored in any other
+        //array that happens to cover it would never be crossed
+        require!(
+            self.starting_tick == TickArray::get_starting_tick_index(tick, tick_spacing),
+            ErrorCode::InvalidTickArrayAccount
+        );
+        let offset = tick
+            .checked_sub(self.starting_tick)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
             .checked_div(tick_spacing)
-            .ok_or(ErrorCode::ArithmeticOverflow)?)
-        .checked_sub(
-            self.starting_tick
-                .checked_div(tick_spacing)
-                .ok_or(ErrorCode::ArithmeticOverflow)?,
-        )
-        .ok_or(ErrorCode::ArithmeticOverflow)?
-        .checked_rem(ticks_per_array_i32)
-        .ok_or(ErrorCode::ArithmeticOverflow)? as usize;
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(offset as usize)
+    }
+
+    pub fn get_tick_info(&self, tick: i32, tick_spacing: i32) -> Result<TickInfo> {
+        Ok(self.ticks[self.tick_offset(tick, tick_spacing)?])
+    }
+
+    pub fn get_tick_info_mutable(&mut self, tick: i32, tick_spacing: i32) -> Result<&mut TickInfo> {
+        let offset = self.tick_offset(tick, tick_spacing)?;
         Ok(&mut self.ticks[offset])
     }
 
-}
\ No newline at end of file
+    //initialized ticks a move to to_tick crosses, in the order the price meets them
+    //moving up crosses ticks in (current_tick, to_tick], moving down crosses ticks in (to_tick, current_tick]
+    //the upper tick of a range comes before its lower one when moving down, otherwise the
+    //liquidity could dip below zero halfway
+    fn crossed_ticks<'a>(
+        &'a self,
+        pool : &'a Pool,
+        to_tick : i32,
+        swap_token_0_for_1 : bool
+    )->impl Iterator<Item = usize> + 'a{
+        let starting_tick = self.starting_tick;
+        (0..TICKS_PER_ARRAY)
+            .map(move |step| if swap_token_0_for_1 { TICKS_PER_ARRAY - 1 - step } else { step })
+            .filter(move |i| {
+                if self.ticks[*i].initialized == 0 {
+                    return false;
+                }
+                let tick = starting_tick + *i as i32 * pool.tick_spacing;
+                if swap_token_0_for_1 {
+                    to_tick < tick && tick <= pool.current_tick
+                } else {
+                    pool.current_tick < tick && tick <= to_tick
+                }
+            })
+    }
+
+    //the liquidity left active once the price moved to to_tick, the part of it that belongs to
+    //unfilled limit orders and how many ticks were crossed, without touching the ticks
+    pub fn quote_cross_ticks(
+        &self,
+        pool : &Pool,
+        to_tick : i32,
+        swap_token_0_for_1 : bool,
+        liquidity : u128,
+        limit_order_liquidity : u128
+    )->Result<(u128, u128, u32)>{
+        let mut liquidity = liquidity;
+        let mut limit_order_liquidity = limit_order_liquidity;
+        let mut ticks_crossed = 0;
+        for i in self.crossed_ticks(pool, to_tick, swap_token_0_for_1) {
+            let tick_info = &self.ticks[i];
+            let (liquidity_net, limit_order_liquidity_net) = if swap_token_0_for_1 {
+                (-tick_info.liquidity_net, -tick_info.limit_order_liquidity_net)
+            } else {
+                (tick_info.liquidity_net, tick_info.limit_order_liquidity_net)
+            };
+            liquidity = add_delta(liquidity, liquidity_net)?;
+            limit_order_liquidity = add_delta(limit_order_liquidity, limit_order_liquidity_net)?;
+            ticks_crossed += 1;
+        }
+        Ok((liquidity, limit_order_liquidity, ticks_crossed))
+    }
+
+    //crosses every initialized tick the price moved over, returns the same as quote_cross_ticks
+    pub fn cross_ticks(
+        &mut self,
+        pool : &Pool,
+        to_tick : i32,
+        swap_token_0_for_1 : bool,
+        liquidity : u128,
+        limit_order_liquidity : u128,
+        cumulatives : &Observation
+    )->Result<(u128, u128, u32)>{
+        let result = self.quote_cross_ticks(pool, to_tick, swap_token_0_for_1, liquidity, limit_order_liquidity)?;
+        let mut crossed = [false; TICKS_PER_ARRAY];
+        for i in self.crossed_ticks(pool, to_tick, swap_token_0_for_1) {
+            crossed[i] = true;
+        }
+        for (i, _) in crossed.iter().enumerate().filter(|(_, crossed)| **crossed) {
+            self.ticks[i].cross(pool, cumulatives);
+        }
+        //only once every tick is crossed, pulling an order out of a tick that is crossed later in
+        //the same loop would leave its liquidity counted on one side only
+        for (i, _) in crossed.iter().enumerate().filter(|(_, crossed)| **crossed) {
+            self.fill_limit_orders(i, swap_token_0_for_1)?;
+        }
+        Ok(result)
+    }
+
+    //the price went fully past every unfilled order on the tick at index, they are taken out of
+    //both of their ticks so the price coming back cannot convert them again
+    //an order never straddles two arrays, see LimitOrder
+    fn fill_limit_orders(&mut self, index : usize, swap_token_0_for_1 : bool)->Result<()>{
+        let tick_info = &mut self.ticks[index];
+        let (liquidity, lower, upper) = if swap_token_0_for_1 {
+            let liquidity = tick_info.limit_order_unfilled_1;
+            tick_info.limit_order_unfilled_1 = 0;
+            tick_info.limit_order_filled_1 = tick_info.limit_order_filled_1
+                .checked_add(liquidity)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            if liquidity > 0 {
+                tick_info.limit_order_fill_count_1 = tick_info.limit_order_fill_count_1.wrapping_add(1);
+            }
+            (liquidity, index, index + 1)
+        } else {
+            let liquidity = tick_info.limit_order_unfilled_0;
+            tick_info.limit_order_unfilled_0 = 0;
+            tick_info.limit_order_filled_0 = tick_info.limit_order_filled_0
+                .checked_add(liquidity)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            if liquidity > 0 {
+                tick_info.limit_order_fill_count_0 = tick_info.limit_order_fill_count_0.wrapping_add(1);
+            }
+            (liquidity, index.wrapping_sub(1), index)
+        };
+        if liquidity == 0 {
+            return Ok(());
+        }
+        self.ticks[lower].remove_limit_order_liquidity(liquidity, true)?;
+        self.ticks[upper].remove_limit_order_liquidity(liquidity, false)?;
+        Ok(())
+    }
+
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick_array(starting_tick : i32)->TickArray{
+        TickArray{
+            pool : Pubkey::default(),
+            starting_tick,
+            ticks : Default::default(),
+            bump : 0,
+            funder : Pubkey::default()
+        }
+    }
+
+    #[test]
+    fn tick_array_layout_matches_the_account_size() {
+        //the limit order fields brought a tick to 181 bytes, the array is read in place, so a
+        //change here changes the layout of every array already on chain
+        assert_eq!(TickInfo::SPACE, 1 + 16 * 5 + 4 + 8 + 16 * 5 + 4 + 4);
+        assert_eq!(TickArray::SPACE, 8 + 32 + 4 + TICKS_PER_ARRAY * TickInfo::SPACE + 1 + 32);
+    }
+
+    #[test]
+    fn get_tick_info_mutable_only_accepts_the_canonical_array() {
+        //with a spacing of 60 the array starting at 0 covers [0, 1800)
+        assert!(tick_array(0).get_tick_info_mutable(600, 60).is_ok());
+        assert!(tick_array(-1800).get_tick_info_mutable(-60, 60).is_ok());
+        //an array starting 5 spacings lower also covers 600, but no swap ever visits it
+        assert_eq!(
+            tick_array(-300).get_tick_info_mutable(600, 60).err().unwrap(),
+            ErrorCode::InvalidTickArrayAccount.into()
+        );
+        assert_eq!(
+            tick_array(1800).get_tick_info_mutable(600, 60).err().unwrap(),
+            ErrorCode::InvalidTickArrayAccount.into()
+        );
+    }
+
+    #[test]
+    fn quote_cross_ticks_matches_cross_ticks_without_writing(){
+        let mut pool = Pool{
+            tick_spacing : 60,
+            current_tick : 1_000,
+            max_liquidity_per_tick : u128::MAX,
+            ..Pool::default()
+        };
+        let cumulatives = Observation::default();
+        let mut tick_array = tick_array(0);
+        for (tick, is_lower) in [(120, true), (600, false), (300, true), (900, false)] {
+            tick_array
+                .get_tick_info_mutable(tick, 60)
+                .unwrap()
+                .update_liquidity(tick, &pool, 1_000, is_lower, Some(&cumulatives))
+                .unwrap();
+        }
+        //fees earned since, so crossing has something to flip
+        pool.fee_growth_global_0_x64 = 7;
+        let before = tick_array.boxed_copy();
+
+        //nothing is active at 1_000, down to 200 crosses 900, 600 and 300 but not 120
+        //and leaves the range 120..600 active
+        let quote = tick_array.quote_cross_ticks(&pool, 200, true, 0, 0).unwrap();
+        assert_eq!(quote, (1_000, 0, 3));
+        assert!(bytemuck::bytes_of(&tick_array) == bytemuck::bytes_of(before.as_ref()));
+
+        let crossed = tick_array.cross_ticks(&pool, 200, true, 0, 0, &cumulatives).unwrap();
+        assert_eq!(crossed, quote);
+        assert!(bytemuck::bytes_of(&tick_array) != bytemuck::bytes_of(before.as_ref()));
+    }
+}
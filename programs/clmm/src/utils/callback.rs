@@ -0,0 +1,65 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+};
+
+use crate::utils::ErrorCode;
+
+//sha256("global:flash_swap_callback")[..8], the anchor discriminator of a `flash_swap_callback` instruction
+pub const FLASH_SWAP_CALLBACK_DISCRIMINATOR : [u8; 8] = [225, 54, 80, 1, 45, 208, 202, 124];
+
+//what the borrower program receives after a flash swap handed out the output
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct FlashSwapCallbackArgs{
+    pub amount_owed : u64, //to send into the input vault, transfer fee of the input mint included
+    pub amount_out : u64,
+    pub swap_token_0_for_1 : bool,
+    pub data : Vec<u8>
+}
+
+//sha256("global:flash_callback")[..8]
+pub const FLASH_CALLBACK_DISCRIMINATOR : [u8; 8] = [242, 2, 17, 61, 70, 198, 54, 59];
+
+//what the borrower program receives after a flash loan, owed amounts include the pool fee
+//and the transfer fee of each mint
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct FlashCallbackArgs{
+    pub amount_0_owed : u64,
+    pub amount_1_owed : u64,
+    pub data : Vec<u8>
+}
+
+//calls the borrower program the way an anchor client would, so the borrower can be a plain
+//anchor program with an instruction matching `discriminator` that takes `args`
+//every account after the program is forwarded in order, with the flags the transaction gave it
+pub fn invoke_callback<'info, T : AnchorSerialize>(
+    callback_program : &AccountInfo<'info>,
+    callback_accounts : &[AccountInfo<'info>],
+    discriminator : &[u8; 8],
+    args : &T
+)->Result<()>{
+    require!(callback_program.executable, ErrorCode::InvalidCallbackProgram);
+    require_keys_neq!(callback_program.key(), crate::ID, ErrorCode::InvalidCallbackProgram);
+
+    let mut data = discriminator.to_vec();
+    args.serialize(&mut data)?;
+    let accounts = callback_accounts
+        .iter()
+        .map(|account| AccountMeta{
+            pubkey : account.key(),
+            is_signer : account.is_signer,
+            is_writable : account.is_writable
+        })
+        .collect();
+    let instruction = Instruction{
+        program_id : callback_program.key(),
+        accounts,
+        data
+    };
+
+    let mut account_infos = callback_accounts.to_vec();
+    account_infos.push(callback_program.clone());
+    invoke(&instruction, &account_infos)?;
+    Ok(())
+}
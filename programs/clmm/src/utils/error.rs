@@ -26,6 +26,64 @@ pub enum ErrorCode {
     InsufficientPoolLiquidity,
     #[msg("SlippageExceeded")]
     SlippageExceeded,
+    #[msg("TickArrayNotEmpty")]
+    TickArrayNotEmpty,
+    #[msg("InvalidTickSpacing")]
+    InvalidTickSpacing,
+    #[msg("LiquidityPerTickExceeded")]
+    LiquidityPerTickExceeded,
+    #[msg("MissingTickArray")]
+    MissingTickArray,
+    #[msg("DuplicateMint")]
+    DuplicateMint,
+    #[msg("InvalidMintOrder")]
+    InvalidMintOrder,
+    #[msg("InvalidSqrtPrice")]
+    InvalidSqrtPrice,
+    #[msg("InvalidVault")]
+    InvalidVault,
+    #[msg("UnsupportedMintExtension")]
+    UnsupportedMintExtension,
+    #[msg("OperationPaused")]
+    OperationPaused,
+    #[msg("InvalidStatus")]
+    InvalidStatus,
+    #[msg("ObservationTooOld")]
+    ObservationTooOld,
+    #[msg("InvalidObservationWindow")]
+    InvalidObservationWindow,
+    #[msg("InvalidObservationCardinality")]
+    InvalidObservationCardinality,
+    #[msg("MissingObservation")]
+    MissingObservation,
+    #[msg("InvalidRoute")]
+    InvalidRoute,
+    #[msg("InvalidCallbackProgram")]
+    InvalidCallbackProgram,
+    #[msg("InsufficientRepayment")]
+    InsufficientRepayment,
+    #[msg("PoolLocked")]
+    PoolLocked,
+    #[msg("InvalidFeeRate")]
+    InvalidFeeRate,
+    #[msg("ReferralFeeTooHigh")]
+    ReferralFeeTooHigh,
+    #[msg("MissingReferralAccount")]
+    MissingReferralAccount,
+    #[msg("InvalidLimitOrder")]
+    InvalidLimitOrder,
+    #[msg("LimitOrderFilled")]
+    LimitOrderFilled,
+    #[msg("InvalidSettleMode")]
+    InvalidSettleMode,
+    #[msg("PositionNotCrossed")]
+    PositionNotCrossed,
+    #[msg("InvalidKeeperBounty")]
+    InvalidKeeperBounty,
+    #[msg("PositionNotEmpty")]
+    PositionNotEmpty,
+    #[msg("MissingConfig")]
+    MissingConfig,
 
 
 }
\ No newline at end of file
@@ -1,6 +1,69 @@
 use crate::utils::ErrorCode;
 use anchor_lang::prelude::*;
 
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = -MIN_TICK;
+
+//liquidity is capped per tick so that even if every usable tick is crossed
+//the sum can never overflow global_liquidity
+pub fn tick_spacing_to_max_liquidity_per_tick(tick_spacing: i32) -> Result<u128> {
+    require!(tick_spacing > 0, ErrorCode::InvalidTickSpacing);
+    let min_tick = (MIN_TICK / tick_spacing) * tick_spacing;
+    let max_tick = (MAX_TICK / tick_spacing) * tick_spacing;
+    let num_ticks = ((max_tick - min_tick) / tick_spacing) as u128 + 1;
+    Ok(u128::MAX / num_ticks)
+}
+
+//pools are always created with token_mint_0 < token_mint_1, clients holding a
+//price quoted the other way round can use this to get the pool order and price
+pub fn canonical_mint_order(
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+    sqrt_price_x96: u128,
+) -> Result<(Pubkey, Pubkey, u128)> {
+    require_keys_neq!(mint_a, mint_b, ErrorCode::DuplicateMint);
+    if mint_a < mint_b {
+        Ok((mint_a, mint_b, sqrt_price_x96))
+    } else {
+        Ok((mint_b, mint_a, invert_sqrt_price_x96(sqrt_price_x96)?))
+    }
+}
+
+//sqrt(1/price) in x96 is 2^192 / sqrt_price_x96, the numerator does not fit in
+//a u128 so this is a plain bit by bit long division
+pub fn invert_sqrt_price_x96(sqrt_price_x96: u128) -> Result<u128> {
+    require!(sqrt_price_x96 > 0, ErrorCode::InvalidSqrtPrice);
+    let mut quotient: u128 = 0;
+    let mut remainder: u128 = 0;
+    for bit in (0..=192).rev() {
+        let carry = remainder >> 127;
+        remainder <<= 1;
+        if bit == 192 {
+            remainder |= 1;
+        }
+        require!(quotient >> 127 == 0, ErrorCode::ArithmeticOverflow);
+        quotient <<= 1;
+        if carry == 1 || remainder >= sqrt_price_x96 {
+            remainder = remainder.wrapping_sub(sqrt_price_x96);
+            quotient |= 1;
+        }
+    }
+    Ok(quotient)
+}
+
+//applies a signed liquidity change to an unsigned liquidity value
+pub fn add_delta(liquidity: u128, liquidity_delta: i128) -> Result<u128> {
+    if liquidity_delta < 0 {
+        liquidity
+            .checked_sub(liquidity_delta.unsigned_abs())
+            .ok_or(ErrorCode::ArithmeticOverflow.into())
+    } else {
+        liquidity
+            .checked_add(liquidity_delta.unsigned_abs())
+            .ok_or(ErrorCode::ArithmeticOverflow.into())
+    }
+}
+
 pub fn get_sqrt_price_from_tick(tick: i32) -> Result<u128> {
     // This is a simplification; real math is logarithmic.
     let base_sqrt_price = 1u128 << 96;
@@ -68,6 +131,23 @@ pub fn get_amount_for_liquidity(
     Ok((amount0,amount1))
 }
 
+//inverse of get_amount_for_liquidity, the most liquidity amount_0 and amount_1 can pay for
+pub fn get_liquidity_for_amounts(
+    current_sqrt_price_x96 : u128,
+    lower_sqrt_price_x96 : u128,
+    upper_sqrt_price_x96 : u128,
+    amount_0 : u64,
+    amount_1 : u64
+)->u128{
+    if current_sqrt_price_x96 >= lower_sqrt_price_x96 && current_sqrt_price_x96 < upper_sqrt_price_x96 {
+        2 * amount_0.min(amount_1) as u128
+    } else if current_sqrt_price_x96 < lower_sqrt_price_x96 {
+        amount_0 as u128
+    } else {
+        amount_1 as u128
+    }
+}
+
 // When an LP opens a position, they say:
 // “I want to provide liquidity between lower_tick and upper_tick.”
 // But the current price may be:
@@ -83,21 +163,26 @@ pub fn get_amount_for_liquidity(
 //CASE :3 => price is above the range in this case When price eventually moves down into the range, swaps will consume token1 first
 
 
+//fee rates are in hundredths of a bip, 1_000 is 0.1%
+pub const FEE_RATE_DENOMINATOR: u32 = 1_000_000;
+
 pub fn swap_segment(
     current_sqrt_price_x96: u128,
     global_liquidity: u128,
     amount_remaining_in: u64,
     swap_token_0_for_1: bool,
-) -> Result<(u64, u64, u128)> {
+    fee_rate: u32,
+) -> Result<(u64, u64, u128, u64)> {
     if global_liquidity == 0 {
         return Err(ErrorCode::InsufficientPoolLiquidity.into());
     }
 
     let amount_in_used = amount_remaining_in;
+    let fee_amount = (amount_in_used as u128 * fee_rate as u128 / FEE_RATE_DENOMINATOR as u128) as u64;
     // This is a simplified calculation and does not represent a real AMM curve.
     let amount_out_calculated = amount_in_used
-        .checked_sub(amount_in_used / 1000)
-        .ok_or(ErrorCode::ArithmeticOverflow)?; // Simple 0.1% fee
+        .checked_sub(fee_amount)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
 
     let new_sqrt_price = if swap_token_0_for_1 {
         current_sqrt_price_x96
@@ -110,5 +195,108 @@ pub fn swap_segment(
             .ok_or(ErrorCode::ArithmeticOverflow)?
     };
 
-    Ok((amount_in_used, amount_out_calculated, new_sqrt_price))
-}
\ No newline at end of file
+    Ok((amount_in_used, amount_out_calculated, new_sqrt_price, fee_amount))
+}
+
+//fee on a flash loan, rounded up so borrowing dust is never free
+pub fn get_flash_fee(amount: u64, fee_rate: u32) -> Result<u64> {
+    let fee = (amount as u128 * fee_rate as u128).div_ceil(FEE_RATE_DENOMINATOR as u128);
+    u64::try_from(fee).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+}
+
+//fee growth is tracked per unit of liquidity as a x64 fixed point number
+pub fn get_fee_growth_delta(fee_amount: u64, liquidity: u128) -> Result<u128> {
+    require!(liquidity > 0, ErrorCode::InsufficientPoolLiquidity);
+    Ok(((fee_amount as u128) << 64) / liquidity)
+}
+//(a * b) >> 64 without the intermediate product overflowing a u128
+pub fn mul_shr_64(a: u128, b: u128) -> Result<u128> {
+    let (a_hi, a_lo) = (a >> 64, a & u64::MAX as u128);
+    let (b_hi, b_lo) = (b >> 64, b & u64::MAX as u128);
+    a_hi.checked_mul(b)
+        .and_then(|x| x.checked_add(a_lo * b_hi))
+        .and_then(|x| x.checked_add((a_lo * b_lo) >> 64))
+        .ok_or(ErrorCode::ArithmeticOverflow.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Q96 : u128 = 1 << 96;
+
+    #[test]
+    fn invert_sqrt_price_x96_inverts_powers_of_two() {
+        assert_eq!(invert_sqrt_price_x96(Q96).unwrap(), Q96);
+        assert_eq!(invert_sqrt_price_x96(Q96 >> 1).unwrap(), Q96 << 1);
+        assert_eq!(invert_sqrt_price_x96(Q96 << 1).unwrap(), Q96 >> 1);
+    }
+
+    #[test]
+    fn invert_sqrt_price_x96_rounds_down() {
+        assert_eq!(invert_sqrt_price_x96(3 * Q96).unwrap(), Q96 / 3);
+        //inverting twice can only lose the rounding of the two divisions
+        let sqrt_price_x96 = Q96 + 123_456_789;
+        let twice = invert_sqrt_price_x96(invert_sqrt_price_x96(sqrt_price_x96).unwrap()).unwrap();
+        assert!(sqrt_price_x96 - twice <= 1);
+    }
+
+    #[test]
+    fn invert_sqrt_price_x96_rejects_zero_and_overflow() {
+        assert_eq!(invert_sqrt_price_x96(0).unwrap_err(), ErrorCode::InvalidSqrtPrice.into());
+        //2^192 does not fit in a u128
+        assert_eq!(invert_sqrt_price_x96(1).unwrap_err(), ErrorCode::ArithmeticOverflow.into());
+    }
+
+    #[test]
+    fn canonical_mint_order_keeps_sorted_mints() {
+        let (mint_a, mint_b) = (Pubkey::new_from_array([1; 32]), Pubkey::new_from_array([2; 32]));
+        assert_eq!(
+            canonical_mint_order(mint_a, mint_b, 2 * Q96).unwrap(),
+            (mint_a, mint_b, 2 * Q96)
+        );
+    }
+
+    #[test]
+    fn canonical_mint_order_swaps_and_inverts() {
+        let (mint_a, mint_b) = (Pubkey::new_from_array([1; 32]), Pubkey::new_from_array([2; 32]));
+        assert_eq!(
+            canonical_mint_order(mint_b, mint_a, 2 * Q96).unwrap(),
+            (mint_a, mint_b, Q96 / 2)
+        );
+    }
+
+    #[test]
+    fn canonical_mint_order_rejects_duplicate_mint() {
+        let mint = Pubkey::new_unique();
+        assert_eq!(
+            canonical_mint_order(mint, mint, Q96).unwrap_err(),
+            ErrorCode::DuplicateMint.into()
+        );
+    }
+
+    #[test]
+    fn fee_growth_delta_round_trips_through_mul_shr_64() {
+        //a position holding all the liquidity is owed the whole fee, rounded down
+        let liquidity = 3_000_000;
+        let fee_growth_delta = get_fee_growth_delta(1_000, liquidity).unwrap();
+        assert_eq!(mul_shr_64(fee_growth_delta, liquidity).unwrap(), 999);
+        assert_eq!(mul_shr_64(fee_growth_delta, liquidity / 3).unwrap(), 333);
+    }
+
+    #[test]
+    fn fee_growth_delta_rejects_zero_liquidity() {
+        assert_eq!(
+            get_fee_growth_delta(1_000, 0).unwrap_err(),
+            ErrorCode::InsufficientPoolLiquidity.into()
+        );
+    }
+
+    #[test]
+    fn mul_shr_64_keeps_the_high_bits() {
+        assert_eq!(mul_shr_64(1 << 64, 5).unwrap(), 5);
+        assert_eq!(mul_shr_64(3 << 63, 3 << 63).unwrap(), 9 << 62);
+        assert_eq!(mul_shr_64(u128::MAX, 1 << 64).unwrap(), u128::MAX);
+        assert_eq!(mul_shr_64(u128::MAX, u128::MAX).unwrap_err(), ErrorCode::ArithmeticOverflow.into());
+    }
+}
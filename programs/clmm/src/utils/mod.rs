@@ -1,5 +1,9 @@
 pub mod error;
 pub mod math;
+pub mod token;
+pub mod callback;
 
 pub use error::*;
-pub use math::*;
\ No newline at end of file
+pub use math::*;
+pub use token::*;
+pub use callback::*;
\ No newline at end of file
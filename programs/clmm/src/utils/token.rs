@@ -0,0 +1,151 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    token::Token,
+    token_2022::spl_token_2022::{
+        self,
+        extension::{
+            transfer_fee::{TransferFeeConfig, MAX_FEE_BASIS_POINTS},
+            BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+        },
+    },
+    token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{states::Pool, utils::ErrorCode};
+
+//mint extensions that do not interfere with how the pool moves or accounts for tokens
+const SUPPORTED_MINT_EXTENSIONS: [ExtensionType; 8] = [
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::InterestBearingConfig,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+    ExtensionType::GroupPointer,
+    ExtensionType::TokenGroup,
+    ExtensionType::GroupMemberPointer,
+    ExtensionType::TokenGroupMember,
+];
+
+pub fn transfer_from_user_to_vault<'info>(
+    authority: &Signer<'info>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    to_vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    transfer_from_user(authority, from, to_vault, mint, token_program, amount)
+}
+
+//the user signs, `to` can be any account of the mint, a vault or someone else's like a referrer's
+pub fn transfer_from_user<'info>(
+    authority: &Signer<'info>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: authority.to_account_info(),
+            },
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+//the pool PDA is the authority of both vaults, so it signs every withdrawal
+pub fn transfer_from_vault_to_user<'info>(
+    pool: &Account<'info, Pool>,
+    from_vault: &InterfaceAccount<'info, TokenAccount>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let pool_seeds = pool.signer_seeds();
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from_vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            &[&pool_seeds],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+//fee withheld by the mint when `pre_fee_amount` is transferred
+pub fn get_transfer_fee(mint: &InterfaceAccount<Mint>, pre_fee_amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() {
+        return Ok(0);
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+
+    let fee = if let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() {
+        transfer_fee_config
+            .calculate_epoch_fee(Clock::get()?.epoch, pre_fee_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+    } else {
+        0
+    };
+    Ok(fee)
+}
+
+//fee to add on top of `post_fee_amount` so that the receiver ends up with exactly `post_fee_amount`
+pub fn get_transfer_inverse_fee(mint: &InterfaceAccount<Mint>, post_fee_amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() {
+        return Ok(0);
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+
+    let fee = if let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() {
+        let epoch = Clock::get()?.epoch;
+        let transfer_fee = transfer_fee_config.get_epoch_fee(epoch);
+        if u16::from(transfer_fee.transfer_fee_basis_points) == MAX_FEE_BASIS_POINTS {
+            u64::from(transfer_fee.maximum_fee)
+        } else {
+            transfer_fee_config
+                .calculate_inverse_epoch_fee(epoch, post_fee_amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+        }
+    } else {
+        0
+    };
+    Ok(fee)
+}
+
+//rejects mints whose extensions would let someone move, freeze or hook into the vault balances
+pub fn is_supported_mint(mint: &InterfaceAccount<Mint>) -> Result<bool> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner == Token::id() {
+        return Ok(true);
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let extensions = mint.get_extension_types()?;
+    Ok(extensions
+        .iter()
+        .all(|extension| SUPPORTED_MINT_EXTENSIONS.contains(extension)))
+}
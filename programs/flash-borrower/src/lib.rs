@@ -0,0 +1,104 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{instruction::{AccountMeta, Instruction}, program::invoke};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+declare_id!("8BwNdjuzi1LC11XKFKR7CvkPuKH8au47tbeCNVK7sLLW");
+
+//first byte of the callback data, tells the borrower how to behave
+pub const REPAY : u8 = 0;
+pub const REPAY_SHORT : u8 = 1;
+//calls remaining_accounts[0] with the rest of the data and remaining_accounts before repaying
+pub const REENTER : u8 = 2;
+
+//stands in for an arbitrage bot in the clmm tests, it gets called back by the pool
+//and pays the vault from the payer's token account
+#[program]
+pub mod flash_borrower {
+    use super::*;
+
+    pub fn flash_swap_callback<'info>(
+        ctx: Context<'_, '_, '_, 'info, Repay<'info>>,
+        amount_owed : u64,
+        _amount_out : u64,
+        _swap_token_0_for_1 : bool,
+        data : Vec<u8>
+    )->Result<()>{
+        let amount = match data.first() {
+            Some(&REPAY_SHORT) => amount_owed.saturating_sub(1),
+            Some(&REENTER) => {
+                reenter(ctx.remaining_accounts, &data[1..])?;
+                amount_owed
+            }
+            _ => amount_owed,
+        };
+        ctx.accounts.repay(amount)
+    }
+
+    pub fn flash_callback(
+        ctx: Context<RepayBoth>,
+        amount_0_owed : u64,
+        amount_1_owed : u64,
+        data : Vec<u8>
+    )->Result<()>{
+        let (amount_0, amount_1) = match data.first() {
+            Some(&REPAY_SHORT) => (amount_0_owed, amount_1_owed.saturating_sub(1)),
+            _ => (amount_0_owed, amount_1_owed),
+        };
+        ctx.accounts.repay_0.repay(amount_0)?;
+        ctx.accounts.repay_1.repay(amount_1)
+    }
+}
+
+#[derive(Accounts)]
+pub struct Repay<'info>{
+    pub payer : Signer<'info>,
+    #[account(mut)]
+    pub payer_token : InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault : InterfaceAccount<'info, TokenAccount>,
+    pub mint : InterfaceAccount<'info, Mint>,
+    pub token_program : Interface<'info, TokenInterface>,
+}
+
+fn reenter(accounts : &[AccountInfo], data : &[u8])->Result<()>{
+    let (program, accounts) = accounts.split_first().ok_or(ErrorCode::AccountNotEnoughKeys)?;
+    let instruction = Instruction{
+        program_id : program.key(),
+        accounts : accounts
+            .iter()
+            .map(|account| AccountMeta{
+                pubkey : account.key(),
+                is_signer : account.is_signer,
+                is_writable : account.is_writable
+            })
+            .collect(),
+        data : data.to_vec()
+    };
+    invoke(&instruction, accounts)?;
+    Ok(())
+}
+
+//one Repay per token, in the order payer, payer_token, vault, mint, token_program
+#[derive(Accounts)]
+pub struct RepayBoth<'info>{
+    pub repay_0 : Repay<'info>,
+    pub repay_1 : Repay<'info>,
+}
+
+impl <'info> Repay<'info>{
+    pub fn repay(&self, amount : u64)->Result<()>{
+        token_interface::transfer_checked(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.payer_token.to_account_info(),
+                    mint: self.mint.to_account_info(),
+                    to: self.vault.to_account_info(),
+                    authority: self.payer.to_account_info(),
+                },
+            ),
+            amount,
+            self.mint.decimals,
+        )
+    }
+}
@@ -2,15 +2,23 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Clmm } from "../target/types/clmm";
 import { assert } from "chai";
-import { PublicKey, SystemProgram, Keypair } from "@solana/web3.js";
+import { PublicKey, SystemProgram, Keypair, Transaction, sendAndConfirmTransaction } from "@solana/web3.js";
 import {
   createMint,
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
   createAccount,
   mintTo,
   getAccount,
   createAssociatedTokenAccount,
+  ExtensionType,
+  getMintLen,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializePermanentDelegateInstruction,
 } from "@solana/spl-token";
+import { canonicalMintOrder } from "../app/pool";
 
 describe("clmm - pool creation and position opening test", () => {
   anchor.setProvider(anchor.AnchorProvider.env());
@@ -24,16 +32,18 @@ describe("clmm - pool creation and position opening test", () => {
   let tokenMint1: PublicKey;
   let poolPda: PublicKey;
   let poolBump: number;
-  let tokenVault0Keypair: Keypair;
-  let tokenVault1Keypair: Keypair;
+  let tokenVault0: PublicKey;
+  let tokenVault1: PublicKey;
   
 
   let userTokenAccount0: PublicKey;
   let userTokenAccount1: PublicKey;
   
   const LOWER_TICK = 0; 
-  const UPPER_TICK = 4000; 
+  const UPPER_TICK = 3600; 
   const LIQUIDITY_AMOUNT = new anchor.BN("100000"); 
+  // becomes the config admin in "admin config and pause switches"
+  const newAdmin = Keypair.generate();
 
   function i32ToLeBytes(value: number): Buffer {
     const buffer = Buffer.allocUnsafe(4);
@@ -47,6 +57,55 @@ describe("clmm - pool creation and position opening test", () => {
     return arrayIdx * ticksPerArrayI32 * tickSpacing;
   }
 
+  function getTickArrayPda(startIndex: number): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("tick_array"), poolPda.toBuffer(), i32ToLeBytes(startIndex)],
+      program.programId
+    )[0];
+  }
+
+  function getObservationPda(): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("observation"), poolPda.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  // the observation account is a fixed header followed by the ring, read in place
+  const OBSERVATION_HEADER_SIZE = 8 + 32 + 1 + 4 + 4 + 4;
+  const OBSERVATION_SIZE = 4 + 8 + 16 + 1;
+
+  async function fetchObservation(index: number) {
+    const info = await program.provider.connection.getAccountInfo(getObservationPda());
+    const offset = OBSERVATION_HEADER_SIZE + index * OBSERVATION_SIZE;
+    return {
+      blockTimestamp: info.data.readUInt32LE(offset),
+      tickCumulative: new anchor.BN(info.data.readBigInt64LE(offset + 4).toString()),
+      initialized: info.data[offset + 28] === 1,
+    };
+  }
+
+  // accounts #[event_cpi] adds to every instruction that emits events
+  function eventAccounts() {
+    return {
+      eventAuthority: PublicKey.findProgramAddressSync(
+        [Buffer.from("__event_authority")],
+        program.programId
+      )[0],
+      program: program.programId,
+    };
+  }
+
+  async function expectError(promise: Promise<unknown>, pattern: RegExp) {
+    try {
+      await promise;
+    } catch (e) {
+      assert.match(e.toString(), pattern);
+      return;
+    }
+    assert.fail("transaction should have failed");
+  }
+
   before(async () => {
     console.log("Setting up test environment (creating mints and deriving PDAs)...");
 
@@ -73,6 +132,9 @@ describe("clmm - pool creation and position opening test", () => {
       6
     );
 
+    // pools only accept token_mint_0 < token_mint_1
+    ({ mint0: tokenMint0, mint1: tokenMint1 } = canonicalMintOrder(tokenMint0, tokenMint1, INITIAL_SQRT_PRICE));
+
 
     [poolPda, poolBump] = PublicKey.findProgramAddressSync(
       [
@@ -84,8 +146,14 @@ describe("clmm - pool creation and position opening test", () => {
       program.programId
     );
 
-    tokenVault0Keypair = anchor.web3.Keypair.generate();
-    tokenVault1Keypair = anchor.web3.Keypair.generate();
+    [tokenVault0] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), poolPda.toBuffer(), tokenMint0.toBuffer()],
+      program.programId
+    );
+    [tokenVault1] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), poolPda.toBuffer(), tokenMint1.toBuffer()],
+      program.programId
+    );
 
     userTokenAccount0 = await createAssociatedTokenAccount(
       program.provider.connection,
@@ -129,19 +197,21 @@ describe("clmm - pool creation and position opening test", () => {
     console.log("Attempting to initialize pool...");
     
     await program.methods
-      .initializePool(TICK_SPACING, INITIAL_SQRT_PRICE)
+      .initalisePool(TICK_SPACING, INITIAL_SQRT_PRICE)
       .accountsStrict({
         payer: program.provider.wallet.publicKey,
         pool: poolPda,
         tokenMint0: tokenMint0,
         tokenMint1: tokenMint1,
-        tokenVault0: tokenVault0Keypair.publicKey,
-        tokenVault1: tokenVault1Keypair.publicKey,
+        tokenVault0: tokenVault0,
+        tokenVault1: tokenVault1,
+        observationState: getObservationPda(),
         systemProgram: anchor.web3.SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
-        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        ...eventAccounts(),
       })
-      .signers([tokenVault0Keypair, tokenVault1Keypair])
       .rpc();
 
     console.log("Pool initialization transaction sent!");
@@ -153,6 +223,66 @@ describe("clmm - pool creation and position opening test", () => {
     assert.equal(poolAccount.tokenMint0.toString(), tokenMint0.toString());
     assert.equal(poolAccount.tokenMint1.toString(), tokenMint1.toString());
     assert.equal(poolAccount.globalLiquidity.toString(), "0");
+    assert.isTrue(poolAccount.unlocked);
+  });
+
+  function initalisePoolWith(
+    mintA: PublicKey,
+    mintB: PublicKey,
+    tokenProgramA: PublicKey = TOKEN_PROGRAM_ID,
+    tokenProgramB: PublicKey = TOKEN_PROGRAM_ID
+  ) {
+    const pool = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer(), i32ToLeBytes(TICK_SPACING)],
+      program.programId
+    )[0];
+    const vault = (mint: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), pool.toBuffer(), mint.toBuffer()],
+        program.programId
+      )[0];
+    return program.methods
+      .initalisePool(TICK_SPACING, INITIAL_SQRT_PRICE)
+      .accountsStrict({
+        payer: program.provider.wallet.publicKey,
+        pool,
+        tokenMint0: mintA,
+        tokenMint1: mintB,
+        tokenVault0: vault(mintA),
+        tokenVault1: vault(mintB),
+        observationState: PublicKey.findProgramAddressSync(
+          [Buffer.from("observation"), pool.toBuffer()],
+          program.programId
+        )[0],
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram0: tokenProgramA,
+        tokenProgram1: tokenProgramB,
+        ...eventAccounts(),
+      })
+      .rpc();
+  }
+
+  it("Refuses a pool pairing a mint with itself", async () => {
+    await expectError(initalisePoolWith(tokenMint0, tokenMint0), /DuplicateMint/);
+  });
+
+  it("Refuses a pool whose mints are not in canonical order", async () => {
+    await expectError(initalisePoolWith(tokenMint1, tokenMint0), /InvalidMintOrder/);
+  });
+
+  it("canonicalMintOrder swaps the mints and inverts a price quoted the other way round", async () => {
+    const sqrtPriceX96 = INITIAL_SQRT_PRICE.muln(2);
+    const order = canonicalMintOrder(tokenMint1, tokenMint0, sqrtPriceX96);
+    assert.isTrue(order.swapped);
+    assert.equal(order.mint0.toString(), tokenMint0.toString());
+    assert.equal(order.mint1.toString(), tokenMint1.toString());
+    assert.equal(order.sqrtPriceX96.toString(), INITIAL_SQRT_PRICE.divn(2).toString());
+
+    const kept = canonicalMintOrder(tokenMint0, tokenMint1, sqrtPriceX96);
+    assert.isFalse(kept.swapped);
+    assert.equal(kept.sqrtPriceX96.toString(), sqrtPriceX96.toString());
+    assert.throws(() => canonicalMintOrder(tokenMint0, tokenMint0, sqrtPriceX96), /DuplicateMint/);
   });
 
   it("Successfully opens a position in the pool", async () => {
@@ -225,18 +355,20 @@ describe("clmm - pool creation and position opening test", () => {
         .accountsStrict({
           pool: poolPda,
           lowerTickArray: lowerTickArrayPda,
-          upperTickArray: upperTickArrayPda,
+          upparTickArray: upperTickArrayPda,
           position: positionPda,
+          observationState: getObservationPda(),
           userToken0: userTokenAccount0,
           userToken1: userTokenAccount1,
-          poolToken0: tokenVault0Keypair.publicKey,
-          poolToken1: tokenVault1Keypair.publicKey,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
           payer: program.provider.wallet.publicKey,
           tokenMint0: tokenMint0,
           tokenMint1: tokenMint1,
           systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
         })
         .rpc();
         console.log("Position opened! Transaction:", tx);
@@ -251,7 +383,7 @@ describe("clmm - pool creation and position opening test", () => {
     assert.equal(positionAccount.owner.toString(), program.provider.wallet.publicKey.toString());
     assert.equal(positionAccount.pool.toString(), poolPda.toString());
     assert.equal(positionAccount.tickLower, LOWER_TICK);
-    assert.equal(positionAccount.tickUpper, UPPER_TICK);
+    assert.equal(positionAccount.tickUppar, UPPER_TICK);
     assert.equal(positionAccount.liquidity.toString(), LIQUIDITY_AMOUNT.toString());
 
     const updatedPoolAccount = await program.account.pool.fetch(poolPda);
@@ -296,4 +428,2521 @@ describe("clmm - pool creation and position opening test", () => {
       console.log("Upper tick array fetch error:", e);
     }
   });
-});
\ No newline at end of file
+
+  describe("account validation", () => {
+    let spoofedVault0: PublicKey;
+
+    before(async () => {
+      // a token account of the right mint that the pool does not own
+      spoofedVault0 = await createAccount(
+        program.provider.connection,
+        program.provider.wallet.payer,
+        tokenMint0,
+        program.provider.wallet.publicKey,
+        Keypair.generate()
+      );
+    });
+
+    it("rejects a spoofed vault when opening a position", async () => {
+      const lowerStart = getTickArrayStartIndex(LOWER_TICK, TICK_SPACING);
+      const upperStart = getTickArrayStartIndex(UPPER_TICK, TICK_SPACING);
+      const [positionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(LOWER_TICK),
+          i32ToLeBytes(UPPER_TICK),
+        ],
+        program.programId
+      );
+
+      await expectError(
+        program.methods
+          .openPosition(
+            program.provider.wallet.publicKey,
+            LOWER_TICK,
+            UPPER_TICK,
+            LIQUIDITY_AMOUNT,
+            lowerStart,
+            upperStart
+          )
+          .accountsStrict({
+            pool: poolPda,
+            lowerTickArray: getTickArrayPda(lowerStart),
+            upparTickArray: getTickArrayPda(upperStart),
+            position: positionPda,
+            observationState: getObservationPda(),
+            userToken0: userTokenAccount0,
+            userToken1: userTokenAccount1,
+            poolToken0: spoofedVault0,
+            poolToken1: tokenVault1,
+            payer: program.provider.wallet.publicKey,
+            tokenMint0: tokenMint0,
+            tokenMint1: tokenMint1,
+            systemProgram: SystemProgram.programId,
+            tokenProgram0: TOKEN_PROGRAM_ID,
+            tokenProgram1: TOKEN_PROGRAM_ID,
+            ...eventAccounts(),
+          })
+          .rpc(),
+        /InvalidVault/
+      );
+    });
+
+    it("rejects a tick array that is not the canonical one for the tick", async () => {
+      // starts 5 spacings below the canonical array and still covers LOWER_TICK,
+      // but a swap would never visit it
+      const misalignedStart = getTickArrayStartIndex(LOWER_TICK, TICK_SPACING) - 5 * TICK_SPACING;
+      const upperStart = getTickArrayStartIndex(UPPER_TICK, TICK_SPACING);
+      const [positionPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(LOWER_TICK),
+          i32ToLeBytes(UPPER_TICK),
+        ],
+        program.programId
+      );
+
+      await expectError(
+        program.methods
+          .openPosition(
+            program.provider.wallet.publicKey,
+            LOWER_TICK,
+            UPPER_TICK,
+            LIQUIDITY_AMOUNT,
+            misalignedStart,
+            upperStart
+          )
+          .accountsStrict({
+            pool: poolPda,
+            lowerTickArray: getTickArrayPda(misalignedStart),
+            upparTickArray: getTickArrayPda(upperStart),
+            position: positionPda,
+            observationState: getObservationPda(),
+            userToken0: userTokenAccount0,
+            userToken1: userTokenAccount1,
+            poolToken0: tokenVault0,
+            poolToken1: tokenVault1,
+            payer: program.provider.wallet.publicKey,
+            tokenMint0: tokenMint0,
+            tokenMint1: tokenMint1,
+            systemProgram: SystemProgram.programId,
+            tokenProgram0: TOKEN_PROGRAM_ID,
+            tokenProgram1: TOKEN_PROGRAM_ID,
+            ...eventAccounts(),
+          })
+          .rpc(),
+        /InvalidTickArrayAccount/
+      );
+    });
+
+    it("rejects a spoofed vault on swap", async () => {
+      const currentStart = getTickArrayStartIndex(0, TICK_SPACING);
+
+      await expectError(
+        program.methods
+          .swap(new anchor.BN(1000), true, new anchor.BN(0), 0)
+          .accountsStrict({
+            pool: poolPda,
+            userToken0: userTokenAccount0,
+            userToken1: userTokenAccount1,
+            poolToken0: spoofedVault0,
+            poolToken1: tokenVault1,
+            tickArray: getTickArrayPda(currentStart),
+            observationState: getObservationPda(),
+            tokenMint0: tokenMint0,
+            tokenMint1: tokenMint1,
+            payer: program.provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+            tokenProgram0: TOKEN_PROGRAM_ID,
+            tokenProgram1: TOKEN_PROGRAM_ID,
+            config: null,
+            referrerToken: null,
+            ...eventAccounts(),
+          })
+          .rpc(),
+        /InvalidVault/
+      );
+    });
+
+    it("rejects a user account of the wrong mint on swap", async () => {
+      const currentStart = getTickArrayStartIndex(0, TICK_SPACING);
+
+      await expectError(
+        program.methods
+          .swap(new anchor.BN(1000), true, new anchor.BN(0), 0)
+          .accountsStrict({
+            pool: poolPda,
+            userToken0: userTokenAccount1,
+            userToken1: userTokenAccount1,
+            poolToken0: tokenVault0,
+            poolToken1: tokenVault1,
+            tickArray: getTickArrayPda(currentStart),
+            observationState: getObservationPda(),
+            tokenMint0: tokenMint0,
+            tokenMint1: tokenMint1,
+            payer: program.provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+            tokenProgram0: TOKEN_PROGRAM_ID,
+            tokenProgram1: TOKEN_PROGRAM_ID,
+            config: null,
+            referrerToken: null,
+            ...eventAccounts(),
+          })
+          .rpc(),
+        /ConstraintTokenMint/
+      );
+    });
+  });
+
+  describe("withdrawals sign with the pool PDA", () => {
+    const lowerStart = () => getTickArrayStartIndex(LOWER_TICK, TICK_SPACING);
+    const upperStart = () => getTickArrayStartIndex(UPPER_TICK, TICK_SPACING);
+    const positionPda = () =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(LOWER_TICK),
+          i32ToLeBytes(UPPER_TICK),
+        ],
+        program.programId
+      )[0];
+
+    async function balances(): Promise<[bigint, bigint]> {
+      const account0 = await getAccount(program.provider.connection, userTokenAccount0);
+      const account1 = await getAccount(program.provider.connection, userTokenAccount1);
+      return [account0.amount, account1.amount];
+    }
+
+    async function swap(amountIn: number, zeroForOne: boolean): Promise<string> {
+      const pool = await program.account.pool.fetch(poolPda);
+      const currentStart = getTickArrayStartIndex(pool.currentTick, TICK_SPACING);
+      const nextStart = zeroForOne
+        ? currentStart - TICKS_PER_ARRAY * TICK_SPACING
+        : currentStart + TICKS_PER_ARRAY * TICK_SPACING;
+
+      return program.methods
+        .swap(new anchor.BN(amountIn), zeroForOne, new anchor.BN(0), 0)
+        .accountsStrict({
+          pool: poolPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tickArray: getTickArrayPda(currentStart),
+          observationState: getObservationPda(),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          payer: program.provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config: null,
+          referrerToken: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          { pubkey: getTickArrayPda(nextStart), isSigner: false, isWritable: true },
+        ])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    // events go through a self CPI, so they sit in the inner instructions rather than the logs
+    async function emittedEvents(signature: string) {
+      const tx = await program.provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const keys = tx.transaction.message.getAccountKeys();
+      return tx.meta.innerInstructions
+        .flatMap((inner) => inner.instructions)
+        .filter((ix) => keys.get(ix.programIdIndex).equals(program.programId))
+        .map((ix) => {
+          const data = anchor.utils.bytes.bs58.decode(ix.data);
+          return program.coder.events.decode(anchor.utils.bytes.base64.encode(data.subarray(8)));
+        })
+        .filter((event) => event !== null);
+    }
+
+    it("decrease_liquidity pays both tokens out of the vaults", async () => {
+      const [before0, before1] = await balances();
+      const half = LIQUIDITY_AMOUNT.divn(2);
+
+      await program.methods
+        .decreaseLiquidity(half, LOWER_TICK, UPPER_TICK)
+        .accountsStrict({
+          observationState: getObservationPda(),
+          payer: program.provider.wallet.publicKey,
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart()),
+          upparTickArray: getTickArrayPda(upperStart()),
+          position: positionPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+
+      const [after0, after1] = await balances();
+      assert.isTrue(after0 > before0, "token 0 should be withdrawn");
+      assert.isTrue(after1 > before1, "token 1 should be withdrawn");
+
+      const position = await program.account.position.fetch(positionPda());
+      assert.equal(position.liquidity.toString(), half.toString());
+      const pool = await program.account.pool.fetch(poolPda);
+      assert.equal(pool.globalLiquidity.toString(), half.toString());
+    });
+
+    it("open_position on an existing position emits LiquidityIncreased", async () => {
+      const extra = new anchor.BN(1000);
+      const liquidityBefore = (await program.account.position.fetch(positionPda())).liquidity;
+      const accounts = {
+        observationState: getObservationPda(),
+        pool: poolPda,
+        lowerTickArray: getTickArrayPda(lowerStart()),
+        upparTickArray: getTickArrayPda(upperStart()),
+        position: positionPda(),
+        userToken0: userTokenAccount0,
+        userToken1: userTokenAccount1,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
+        payer: program.provider.wallet.publicKey,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
+        systemProgram: SystemProgram.programId,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        ...eventAccounts(),
+      };
+
+      const signature = await program.methods
+        .openPosition(program.provider.wallet.publicKey, LOWER_TICK, UPPER_TICK, extra, lowerStart(), upperStart())
+        .accountsStrict(accounts)
+        .rpc({ commitment: "confirmed" });
+
+      const [event] = await emittedEvents(signature);
+      assert.equal(event.name, "liquidityIncreased");
+      assert.equal(event.data.position.toString(), positionPda().toString());
+      assert.equal(event.data.liquidity.toString(), extra.toString());
+      const position = await program.account.position.fetch(positionPda());
+      assert.equal(position.liquidity.toString(), liquidityBefore.add(extra).toString());
+
+      // back to where the following tests expect the position
+      await program.methods
+        .decreaseLiquidity(extra, LOWER_TICK, UPPER_TICK)
+        .accountsStrict(accounts)
+        .rpc();
+    });
+
+    it("swap pays token 0 out of vault 0", async () => {
+      const [before0, before1] = await balances();
+      await swap(1000, false);
+      const [after0, after1] = await balances();
+
+      assert.equal((before1 - after1).toString(), "1000");
+      assert.isTrue(after0 > before0, "token 0 should be paid out");
+    });
+
+    it("swap pays token 1 out of vault 1", async () => {
+      const [before0, before1] = await balances();
+      const signature = await swap(1000, true);
+      const [after0, after1] = await balances();
+
+      assert.equal((before0 - after0).toString(), "1000");
+      assert.isTrue(after1 > before1, "token 1 should be paid out");
+
+      const [event] = await emittedEvents(signature);
+      const pool = await program.account.pool.fetch(poolPda);
+      assert.equal(event.name, "swapEvent");
+      assert.equal(event.data.amountIn.toString(), "1000");
+      assert.equal(event.data.amountOut.toString(), (after1 - before1).toString());
+      assert.equal(event.data.sqrtPriceX96.toString(), pool.sqrtPriceX96.toString());
+      assert.equal(event.data.tick, pool.currentTick);
+    });
+
+    it("quote_swap returns what the same swap pays out", async () => {
+      const pool = await program.account.pool.fetch(poolPda);
+      const currentStart = getTickArrayStartIndex(pool.currentTick, TICK_SPACING);
+      const nextStart = currentStart + TICKS_PER_ARRAY * TICK_SPACING;
+
+      const quote = await program.methods
+        .quoteSwap(new anchor.BN(1000), false)
+        .accountsStrict({
+          pool: poolPda,
+          tickArray: getTickArrayPda(currentStart),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          config: null,
+        })
+        .remainingAccounts([
+          { pubkey: getTickArrayPda(nextStart), isSigner: false, isWritable: false },
+        ])
+        .view();
+
+      // quoting must not move the pool
+      const poolAfterQuote = await program.account.pool.fetch(poolPda);
+      assert.equal(poolAfterQuote.sqrtPriceX96.toString(), pool.sqrtPriceX96.toString());
+
+      const [before0] = await balances();
+      await swap(1000, false);
+      const [after0] = await balances();
+      const poolAfterSwap = await program.account.pool.fetch(poolPda);
+
+      assert.equal(quote.amountOut.toString(), (after0 - before0).toString());
+      assert.equal(quote.sqrtPriceX96.toString(), poolAfterSwap.sqrtPriceX96.toString());
+      assert.equal(quote.tick, poolAfterSwap.currentTick);
+    });
+
+    // reentry is an instruction the borrower sends back into clmm from inside its callback
+    function flashSwap(repayMode: number, reentry?: anchor.web3.TransactionInstruction) {
+      const currentStart = getTickArrayStartIndex(0, TICK_SPACING);
+      const borrower = anchor.workspace.flashBorrower.programId as PublicKey;
+      return program.methods
+        .flashSwap(
+          new anchor.BN(1000),
+          true,
+          new anchor.BN(0),
+          1,
+          Buffer.concat([Buffer.from([repayMode]), reentry?.data ?? Buffer.alloc(0)])
+        )
+        .accountsStrict({
+          pool: poolPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tickArray: getTickArrayPda(currentStart),
+          observationState: getObservationPda(),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          payer: program.provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config: null,
+          referrerToken: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          // tick arrays
+          {
+            pubkey: getTickArrayPda(currentStart - TICKS_PER_ARRAY * TICK_SPACING),
+            isSigner: false,
+            isWritable: true,
+          },
+          // borrower program and the accounts its callback takes
+          { pubkey: borrower, isSigner: false, isWritable: false },
+          { pubkey: program.provider.wallet.publicKey, isSigner: true, isWritable: false },
+          { pubkey: userTokenAccount0, isSigner: false, isWritable: true },
+          { pubkey: tokenVault0, isSigner: false, isWritable: true },
+          { pubkey: tokenMint0, isSigner: false, isWritable: false },
+          { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+          ...(reentry
+            ? [{ pubkey: reentry.programId, isSigner: false, isWritable: false }, ...reentry.keys]
+            : []),
+        ])
+        .rpc();
+    }
+
+    it("flash_swap fails when the borrower repays less than it owes", async () => {
+      await expectError(flashSwap(1), /InsufficientRepayment/);
+    });
+
+    it("flash_swap pays out first and collects the input through the callback", async () => {
+      const [before0, before1] = await balances();
+      await flashSwap(0);
+      const [after0, after1] = await balances();
+
+      assert.equal((before0 - after0).toString(), "1000");
+      assert.isTrue(after1 > before1, "token 1 should be paid out");
+    });
+
+    it("the runtime rejects a flash callback that re-enters clmm", async () => {
+      const reentry = await program.methods
+        .collectFees()
+        .accountsStrict({
+          payer: program.provider.wallet.publicKey,
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart()),
+          upparTickArray: getTickArrayPda(upperStart()),
+          position: positionPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .instruction();
+
+      // the runtime refuses clmm -> borrower -> clmm before any clmm code runs, so a callback
+      // never sees PoolLocked and this only covers the runtime rule, Pool::lock itself is
+      // covered by the unit tests in pool.rs
+      await expectError(flashSwap(2, reentry), /reentrancy not allowed/i);
+    });
+
+    function flash(amount: number, repayMode: number) {
+      const borrower = anchor.workspace.flashBorrower.programId as PublicKey;
+      const payer = program.provider.wallet.publicKey;
+      return program.methods
+        .flash(new anchor.BN(amount), new anchor.BN(amount), Buffer.from([repayMode]))
+        .accountsStrict({
+          pool: poolPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          payer,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          { pubkey: borrower, isSigner: false, isWritable: false },
+          { pubkey: payer, isSigner: true, isWritable: false },
+          { pubkey: userTokenAccount0, isSigner: false, isWritable: true },
+          { pubkey: tokenVault0, isSigner: false, isWritable: true },
+          { pubkey: tokenMint0, isSigner: false, isWritable: false },
+          { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+          { pubkey: payer, isSigner: true, isWritable: false },
+          { pubkey: userTokenAccount1, isSigner: false, isWritable: true },
+          { pubkey: tokenVault1, isSigner: false, isWritable: true },
+          { pubkey: tokenMint1, isSigner: false, isWritable: false },
+          { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+        ])
+        .rpc();
+    }
+
+    it("flash fails when the loan plus fee does not come back", async () => {
+      await expectError(flash(10_000, 1), /InsufficientRepayment/);
+    });
+
+    it("flash lends both vaults and credits the fee to the LPs", async () => {
+      const poolBefore = await program.account.pool.fetch(poolPda);
+      const [before0, before1] = await balances();
+      await flash(10_000, 0);
+      const [after0, after1] = await balances();
+      const poolAfter = await program.account.pool.fetch(poolPda);
+
+      // 0.1% of 10_000 each way
+      assert.equal((before0 - after0).toString(), "10");
+      assert.equal((before1 - after1).toString(), "10");
+      assert.isTrue(poolAfter.feeGrowthGlobal0X64.gt(poolBefore.feeGrowthGlobal0X64));
+      assert.isTrue(poolAfter.feeGrowthGlobal1X64.gt(poolBefore.feeGrowthGlobal1X64));
+    });
+
+    it("collect_fees settles the swap fees and resets what the position is owed", async () => {
+      const pool = await program.account.pool.fetch(poolPda);
+      assert.isTrue(pool.feeGrowthGlobal0X64.gtn(0), "token 0 fees should have accrued");
+      assert.isTrue(pool.feeGrowthGlobal1X64.gtn(0), "token 1 fees should have accrued");
+
+      await program.methods
+        .collectFees()
+        .accountsStrict({
+          payer: program.provider.wallet.publicKey,
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart()),
+          upparTickArray: getTickArrayPda(upperStart()),
+          position: positionPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+
+      const position = await program.account.position.fetch(positionPda());
+      assert.equal(position.tokensOwed0.toString(), "0");
+      assert.equal(position.tokensOwed1.toString(), "0");
+      assert.equal(position.feeGrowthInside0LastX64.toString(), pool.feeGrowthGlobal0X64.toString());
+    });
+
+    it("snapshot_cumulatives_inside counts the seconds the range was active", async () => {
+      const snapshot = () =>
+        program.methods
+          .snapshotCumulativesInside(LOWER_TICK, UPPER_TICK)
+          .accountsStrict({
+            pool: poolPda,
+            observationState: getObservationPda(),
+            lowerTickArray: getTickArrayPda(lowerStart()),
+            upparTickArray: getTickArrayPda(upperStart()),
+          })
+          .view();
+
+      const first = await snapshot();
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+      const second = await snapshot();
+
+      // the current tick sits inside the range, so every second counts
+      assert.isAtLeast(second.secondsInside - first.secondsInside, 1);
+      assert.isTrue(
+        second.secondsPerLiquidityInsideX64.gt(first.secondsPerLiquidityInsideX64),
+        "seconds per liquidity should grow while in range"
+      );
+    });
+
+    it("close_position pays out the remaining liquidity and closes the position", async () => {
+      const [before0, before1] = await balances();
+
+      await program.methods
+        .closePosition(LOWER_TICK, UPPER_TICK, lowerStart(), upperStart())
+        .accountsStrict({
+          observationState: getObservationPda(),
+          owner: program.provider.wallet.publicKey,
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart()),
+          upperTickArray: getTickArrayPda(upperStart()),
+          position: positionPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+
+      const [after0, after1] = await balances();
+      assert.isTrue(after0 > before0, "token 0 should be withdrawn");
+      assert.isTrue(after1 > before1, "token 1 should be withdrawn");
+      assert.isNull(await program.provider.connection.getAccountInfo(positionPda()));
+
+      const pool = await program.account.pool.fetch(poolPda);
+      assert.equal(pool.globalLiquidity.toString(), "0");
+    });
+  });
+  describe("admin config and pause switches", () => {
+    const configPda = () =>
+      PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId)[0];
+    const programDataPda = () =>
+      PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+      )[0];
+
+    function swapAccounts() {
+      return {
+        pool: poolPda,
+        userToken0: userTokenAccount0,
+        userToken1: userTokenAccount1,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
+        tickArray: getTickArrayPda(getTickArrayStartIndex(0, TICK_SPACING)),
+        observationState: getObservationPda(),
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
+        payer: program.provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        config: null,
+        referrerToken: null,
+        ...eventAccounts(),
+      };
+    }
+
+    it("lets the upgrade authority create the config", async () => {
+      await program.methods
+        .initializeConfig(program.provider.wallet.publicKey)
+        .accountsStrict({
+          payer: program.provider.wallet.publicKey,
+          config: configPda(),
+          program: program.programId,
+          programData: programDataPda(),
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      const config = await program.account.globalConfig.fetch(configPda());
+      assert.equal(config.admin.toString(), program.provider.wallet.publicKey.toString());
+    });
+
+    it("hands admin over only once the new admin accepts", async () => {
+      await program.methods
+        .proposeAdmin(newAdmin.publicKey)
+        .accountsStrict({ admin: program.provider.wallet.publicKey, config: configPda() })
+        .rpc();
+
+      let config = await program.account.globalConfig.fetch(configPda());
+      assert.equal(config.admin.toString(), program.provider.wallet.publicKey.toString());
+      assert.equal(config.pendingAdmin.toString(), newAdmin.publicKey.toString());
+
+      await program.methods
+        .acceptAdmin()
+        .accountsStrict({ pendingAdmin: newAdmin.publicKey, config: configPda() })
+        .signers([newAdmin])
+        .rpc();
+
+      config = await program.account.globalConfig.fetch(configPda());
+      assert.equal(config.admin.toString(), newAdmin.publicKey.toString());
+      assert.equal(config.pendingAdmin.toString(), PublicKey.default.toString());
+    });
+
+    it("rejects pool status changes from anyone but the admin", async () => {
+      await expectError(
+        program.methods
+          .setPoolStatus(1)
+          .accountsStrict({
+            admin: program.provider.wallet.publicKey,
+            config: configPda(),
+            pool: poolPda,
+          })
+          .rpc(),
+        /Unauthorized/
+      );
+    });
+
+    it("blocks swaps while the swap switch is set", async () => {
+      await program.methods
+        .setPoolStatus(1)
+        .accountsStrict({ admin: newAdmin.publicKey, config: configPda(), pool: poolPda })
+        .signers([newAdmin])
+        .rpc();
+
+      await expectError(
+        program.methods
+          .swap(new anchor.BN(1000), true, new anchor.BN(0), 0)
+          .accountsStrict(swapAccounts())
+          .rpc(),
+        /OperationPaused/
+      );
+
+      await program.methods
+        .setPoolStatus(0)
+        .accountsStrict({ admin: newAdmin.publicKey, config: configPda(), pool: poolPda })
+        .signers([newAdmin])
+        .rpc();
+      const pool = await program.account.pool.fetch(poolPda);
+      assert.equal(pool.status, 0);
+    });
+
+    it("rejects unknown status bits", async () => {
+      await expectError(
+        program.methods
+          .setPoolStatus(1 << 7)
+          .accountsStrict({ admin: newAdmin.publicKey, config: configPda(), pool: poolPda })
+          .signers([newAdmin])
+          .rpc(),
+        /InvalidStatus/
+      );
+    });
+
+    it("rejects dynamic fee bounds with min above max", async () => {
+      await expectError(
+        program.methods
+          .setFeeBounds(2_000, 1_000)
+          .accountsStrict({ admin: newAdmin.publicKey, config: configPda() })
+          .signers([newAdmin])
+          .rpc(),
+        /InvalidFeeRate/
+      );
+    });
+
+    it("switches a pool between the fixed and the dynamic fee", async () => {
+      await program.methods
+        .setFeeBounds(300, 5_000)
+        .accountsStrict({ admin: newAdmin.publicKey, config: configPda() })
+        .signers([newAdmin])
+        .rpc();
+      const setDynamicFee = (enabled: boolean) =>
+        program.methods
+          .setDynamicFee(enabled)
+          .accountsStrict({ admin: newAdmin.publicKey, config: configPda(), pool: poolPda })
+          .signers([newAdmin])
+          .rpc();
+
+      await setDynamicFee(true);
+      let pool = await program.account.pool.fetch(poolPda);
+      assert.isTrue(pool.dynamicFee);
+      assert.equal(pool.feeRate, 300);
+
+      await setDynamicFee(false);
+      pool = await program.account.pool.fetch(poolPda);
+      assert.isFalse(pool.dynamicFee);
+      assert.equal(pool.feeRate, 1_000);
+    });
+  });
+  describe("oracle", () => {
+    it("observe returns the twap over the requested window", async () => {
+      // let at least one full second pass without any swap
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+      const pool = await program.account.pool.fetch(poolPda);
+
+      const result = await program.methods
+        .observe([1, 0])
+        .accountsStrict({ pool: poolPda, observationState: getObservationPda() })
+        .view();
+
+      assert.equal(result.tickCumulatives.length, 2);
+      assert.equal(result.twapTick, pool.currentTick);
+      assert.equal(
+        result.tickCumulatives[1].sub(result.tickCumulatives[0]).toNumber(),
+        pool.currentTick
+      );
+    });
+
+    it("rejects a window that is not oldest first", async () => {
+      await expectError(
+        program.methods
+          .observe([0, 1])
+          .accountsStrict({ pool: poolPda, observationState: getObservationPda() })
+          .view(),
+        /InvalidObservationWindow/
+      );
+    });
+    it("lets anyone grow the observation buffer", async () => {
+      const payer = Keypair.generate();
+      const sig = await program.provider.connection.requestAirdrop(payer.publicKey, 1_000_000_000);
+      await program.provider.connection.confirmTransaction(sig);
+
+      await program.methods
+        .increaseObservationCardinality(150)
+        .accountsStrict({
+          payer: payer.publicKey,
+          pool: poolPda,
+          observationState: getObservationPda(),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc();
+
+      const observationState = await program.account.observationState.fetch(getObservationPda());
+      assert.equal(observationState.observationCardinalityNext, 150);
+      // the new slots only join the ring once writes wrap around to them
+      assert.equal(observationState.observationCardinality, 100);
+      const info = await program.provider.connection.getAccountInfo(getObservationPda());
+      assert.equal(info.data.length, OBSERVATION_HEADER_SIZE + 150 * OBSERVATION_SIZE);
+      assert.isFalse((await fetchObservation(149)).initialized);
+    });
+
+    it("grows the observation buffer past the old 300 slot cap", async () => {
+      await program.methods
+        .increaseObservationCardinality(400)
+        .accountsStrict({
+          payer: program.provider.wallet.publicKey,
+          pool: poolPda,
+          observationState: getObservationPda(),
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      const observationState = await program.account.observationState.fetch(getObservationPda());
+      assert.equal(observationState.observationCardinalityNext, 400);
+      const info = await program.provider.connection.getAccountInfo(getObservationPda());
+      assert.equal(info.data.length, OBSERVATION_HEADER_SIZE + 400 * OBSERVATION_SIZE);
+    });
+
+    it("grows by at most 10KiB per instruction", async () => {
+      // a 24h window at one observation a second takes several calls
+      await expectError(
+        program.methods
+          .increaseObservationCardinality(86_400)
+          .accountsStrict({
+            payer: program.provider.wallet.publicKey,
+            pool: poolPda,
+            observationState: getObservationPda(),
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc(),
+        /AccountReallocExceedsLimit/
+      );
+    });
+
+    it("rejects shrinking the observation buffer", async () => {
+      await expectError(
+        program.methods
+          .increaseObservationCardinality(120)
+          .accountsStrict({
+            payer: program.provider.wallet.publicKey,
+            pool: poolPda,
+            observationState: getObservationPda(),
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc(),
+        /InvalidObservationCardinality/
+      );
+    });
+  });
+  describe("multi-hop routes", () => {
+    let tokenMint2: PublicKey;
+    let userTokenAccount2: PublicKey;
+    let poolB: PublicKey;
+    let poolBMint0: PublicKey;
+    let poolBMint1: PublicKey;
+    const B_LOWER_TICK = -1800;
+    const B_UPPER_TICK = 1200;
+
+    const tickArrayFor = (pool: PublicKey, startIndex: number) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("tick_array"), pool.toBuffer(), i32ToLeBytes(startIndex)],
+        program.programId
+      )[0];
+    const observationFor = (pool: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("observation"), pool.toBuffer()],
+        program.programId
+      )[0];
+    const vaultFor = (pool: PublicKey, mint: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), pool.toBuffer(), mint.toBuffer()],
+        program.programId
+      )[0];
+    const positionFor = (pool: PublicKey, lower: number, upper: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          pool.toBuffer(),
+          i32ToLeBytes(lower),
+          i32ToLeBytes(upper),
+        ],
+        program.programId
+      )[0];
+
+    async function openPosition(
+      pool: PublicKey,
+      mint0: PublicKey,
+      mint1: PublicKey,
+      user0: PublicKey,
+      user1: PublicKey,
+      lower: number,
+      upper: number
+    ) {
+      const lowerStart = getTickArrayStartIndex(lower, TICK_SPACING);
+      const upperStart = getTickArrayStartIndex(upper, TICK_SPACING);
+      await program.methods
+        .openPosition(program.provider.wallet.publicKey, lower, upper, LIQUIDITY_AMOUNT, lowerStart, upperStart)
+        .accountsStrict({
+          pool,
+          lowerTickArray: tickArrayFor(pool, lowerStart),
+          upparTickArray: tickArrayFor(pool, upperStart),
+          position: positionFor(pool, lower, upper),
+          observationState: observationFor(pool),
+          userToken0: user0,
+          userToken1: user1,
+          poolToken0: vaultFor(pool, mint0),
+          poolToken1: vaultFor(pool, mint1),
+          payer: program.provider.wallet.publicKey,
+          tokenMint0: mint0,
+          tokenMint1: mint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+    }
+
+    // token 0 -> token 1 through the main pool, then token 1 -> token 2 through pool B
+    function route(amountIn: number, amountOutMinimum: number) {
+      const bZeroForOne = poolBMint0.equals(tokenMint1);
+      const hop = (
+        pool: PublicKey,
+        mintIn: PublicKey,
+        mintOut: PublicKey,
+        tickArrays: PublicKey[]
+      ) =>
+        [
+          pool,
+          vaultFor(pool, mintIn),
+          vaultFor(pool, mintOut),
+          mintIn,
+          mintOut,
+          observationFor(pool),
+          ...tickArrays,
+        ].map((pubkey, i) => ({ pubkey, isSigner: false, isWritable: i !== 3 && i !== 4 }));
+
+      return program.methods
+        .swapRoute(new anchor.BN(amountIn), new anchor.BN(amountOutMinimum), Buffer.from([2, 2]))
+        .accountsStrict({
+          payer: program.provider.wallet.publicKey,
+          userTokenIn: userTokenAccount0,
+          userTokenOut: userTokenAccount2,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgram2022: TOKEN_2022_PROGRAM_ID,
+          config: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          ...hop(poolPda, tokenMint0, tokenMint1, [
+            tickArrayFor(poolPda, 0),
+            tickArrayFor(poolPda, -TICKS_PER_ARRAY * TICK_SPACING),
+          ]),
+          ...hop(poolB, tokenMint1, tokenMint2, [
+            tickArrayFor(poolB, 0),
+            tickArrayFor(poolB, bZeroForOne ? -TICKS_PER_ARRAY * TICK_SPACING : TICKS_PER_ARRAY * TICK_SPACING),
+          ]),
+        ])
+        .rpc();
+    }
+
+    before(async () => {
+      const connection = program.provider.connection;
+      const payer = program.provider.wallet.payer;
+      const wallet = program.provider.wallet.publicKey;
+
+      tokenMint2 = await createMint(connection, payer, wallet, null, 6);
+      userTokenAccount2 = await createAssociatedTokenAccount(connection, payer, tokenMint2, wallet);
+      await mintTo(connection, payer, tokenMint2, userTokenAccount2, wallet, 1000000000);
+
+      ({ mint0: poolBMint0, mint1: poolBMint1 } = canonicalMintOrder(tokenMint1, tokenMint2, INITIAL_SQRT_PRICE));
+      poolB = PublicKey.findProgramAddressSync(
+        [Buffer.from("pool"), poolBMint0.toBuffer(), poolBMint1.toBuffer(), i32ToLeBytes(TICK_SPACING)],
+        program.programId
+      )[0];
+
+      await program.methods
+        .initalisePool(TICK_SPACING, INITIAL_SQRT_PRICE)
+        .accountsStrict({
+          payer: wallet,
+          pool: poolB,
+          tokenMint0: poolBMint0,
+          tokenMint1: poolBMint1,
+          tokenVault0: vaultFor(poolB, poolBMint0),
+          tokenVault1: vaultFor(poolB, poolBMint1),
+          observationState: observationFor(poolB),
+          systemProgram: SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+
+      const userFor = (mint: PublicKey) => (mint.equals(tokenMint1) ? userTokenAccount1 : userTokenAccount2);
+      await openPosition(
+        poolB,
+        poolBMint0,
+        poolBMint1,
+        userFor(poolBMint0),
+        userFor(poolBMint1),
+        B_LOWER_TICK,
+        B_UPPER_TICK
+      );
+      // the main pool was emptied by the close_position test
+      await openPosition(
+        poolPda,
+        tokenMint0,
+        tokenMint1,
+        userTokenAccount0,
+        userTokenAccount1,
+        LOWER_TICK,
+        UPPER_TICK
+      );
+    });
+
+    it("enforces amount_out_minimum on the final hop only", async () => {
+      await expectError(route(1000, 1000), /SlippageExceeded/);
+    });
+
+    it("chains the swaps and pays only the last output to the user", async () => {
+      const connection = program.provider.connection;
+      const before0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const before1 = (await getAccount(connection, userTokenAccount1)).amount;
+      const before2 = (await getAccount(connection, userTokenAccount2)).amount;
+
+      await route(1000, 1);
+
+      const after0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const after1 = (await getAccount(connection, userTokenAccount1)).amount;
+      const after2 = (await getAccount(connection, userTokenAccount2)).amount;
+      assert.equal((before0 - after0).toString(), "1000");
+      // the intermediate token never touches the user account
+      assert.equal(after1.toString(), before1.toString());
+      assert.isTrue(after2 > before2, "token 2 should be paid out");
+    });
+  });
+  describe("referral fees", () => {
+    const REFERRAL_LOWER_TICK = -3600;
+    const REFERRAL_UPPER_TICK = 5400;
+    const referrer = Keypair.generate();
+    let referrerToken0: PublicKey;
+    let referrerToken1: PublicKey;
+    const configPda = () =>
+      PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId)[0];
+
+    before(async () => {
+      const connection = program.provider.connection;
+      const payer = program.provider.wallet.payer;
+      referrerToken0 = await createAssociatedTokenAccount(connection, payer, tokenMint0, referrer.publicKey);
+      referrerToken1 = await createAssociatedTokenAccount(connection, payer, tokenMint1, referrer.publicKey);
+
+      // a wide position so the swaps below have liquidity wherever the earlier tests left the price
+      const lowerStart = getTickArrayStartIndex(REFERRAL_LOWER_TICK, TICK_SPACING);
+      const upperStart = getTickArrayStartIndex(REFERRAL_UPPER_TICK, TICK_SPACING);
+      const [position] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(REFERRAL_LOWER_TICK),
+          i32ToLeBytes(REFERRAL_UPPER_TICK),
+        ],
+        program.programId
+      );
+      await program.methods
+        .openPosition(
+          program.provider.wallet.publicKey,
+          REFERRAL_LOWER_TICK,
+          REFERRAL_UPPER_TICK,
+          LIQUIDITY_AMOUNT,
+          lowerStart,
+          upperStart
+        )
+        .accountsStrict({
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart),
+          upparTickArray: getTickArrayPda(upperStart),
+          position,
+          observationState: getObservationPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          payer: program.provider.wallet.publicKey,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+    });
+
+    // token 1 -> token 0, the referral comes out of the input or the output depending on referrerToken's mint
+    async function swapWithReferral(referralFeeBps: number, referrerToken: PublicKey) {
+      const pool = await program.account.pool.fetch(poolPda);
+      const currentStart = getTickArrayStartIndex(pool.currentTick, TICK_SPACING);
+      return program.methods
+        .swap(new anchor.BN(10_000), false, new anchor.BN(0), referralFeeBps)
+        .accountsStrict({
+          pool: poolPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tickArray: getTickArrayPda(currentStart),
+          observationState: getObservationPda(),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          payer: program.provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config: configPda(),
+          referrerToken,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          {
+            pubkey: getTickArrayPda(currentStart + TICKS_PER_ARRAY * TICK_SPACING),
+            isSigner: false,
+            isWritable: true,
+          },
+        ])
+        .rpc();
+    }
+
+    const referrerBalance = async (account: PublicKey) =>
+      (await getAccount(program.provider.connection, account)).amount;
+
+    it("rejects a referral fee cap above MAX_REFERRAL_FEE_BPS", async () => {
+      await expectError(
+        program.methods
+          .setMaxReferralFee(501)
+          .accountsStrict({ admin: newAdmin.publicKey, config: configPda() })
+          .signers([newAdmin])
+          .rpc(),
+        /ReferralFeeTooHigh/
+      );
+      const config = await program.account.globalConfig.fetch(configPda());
+      assert.equal(config.maxReferralFeeBps, 100);
+    });
+
+    it("rejects a referral fee above the config cap", async () => {
+      await expectError(swapWithReferral(101, referrerToken1), /ReferralFeeTooHigh/);
+    });
+
+    it("takes the referral fee from the input", async () => {
+      await swapWithReferral(50, referrerToken1);
+      // 0.5% of 10_000 token 1
+      assert.equal((await referrerBalance(referrerToken1)).toString(), "50");
+    });
+
+    it("takes the referral fee from the output", async () => {
+      const before0 = (await getAccount(program.provider.connection, userTokenAccount0)).amount;
+      await swapWithReferral(50, referrerToken0);
+      const after0 = (await getAccount(program.provider.connection, userTokenAccount0)).amount;
+
+      const referralFee = await referrerBalance(referrerToken0);
+      assert.isTrue(referralFee > 0n, "the referrer should be paid in token 0");
+      // the referral and the user share the pool output, 0.5% and 99.5%
+      assert.equal(((after0 - before0 + referralFee) * 5n / 1000n).toString(), referralFee.toString());
+    });
+  });
+  describe("dynamic fee", () => {
+    const configPda = () =>
+      PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId)[0];
+
+    const setFeeBounds = (min: number, max: number) =>
+      program.methods
+        .setFeeBounds(min, max)
+        .accountsStrict({ admin: newAdmin.publicKey, config: configPda() })
+        .signers([newAdmin])
+        .rpc();
+    const setDynamicFee = (enabled: boolean) =>
+      program.methods
+        .setDynamicFee(enabled)
+        .accountsStrict({ admin: newAdmin.publicKey, config: configPda(), pool: poolPda })
+        .signers([newAdmin])
+        .rpc();
+
+    // the tick arrays a swap in either direction walks from the current price
+    async function swapTickArrays(zeroForOne: boolean) {
+      const pool = await program.account.pool.fetch(poolPda);
+      const currentStart = getTickArrayStartIndex(pool.currentTick, TICK_SPACING);
+      const nextStart = zeroForOne
+        ? currentStart - TICKS_PER_ARRAY * TICK_SPACING
+        : currentStart + TICKS_PER_ARRAY * TICK_SPACING;
+      return [currentStart, nextStart];
+    }
+
+    // the wide referral position provides the liquidity, every swap moves the price 1000 ticks
+    async function swap(zeroForOne: boolean, config: PublicKey | null = configPda()) {
+      const [currentStart, nextStart] = await swapTickArrays(zeroForOne);
+      return program.methods
+        .swap(new anchor.BN(1000), zeroForOne, new anchor.BN(0), 0)
+        .accountsStrict({
+          pool: poolPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tickArray: getTickArrayPda(currentStart),
+          observationState: getObservationPda(),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          payer: program.provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config,
+          referrerToken: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          { pubkey: getTickArrayPda(nextStart), isSigner: false, isWritable: true },
+        ])
+        .rpc();
+    }
+
+    // how many initialized ticks the same swap would cross
+    async function ticksCrossed(zeroForOne: boolean): Promise<number> {
+      const [currentStart, nextStart] = await swapTickArrays(zeroForOne);
+      const quote = await program.methods
+        .quoteSwap(new anchor.BN(1000), zeroForOne)
+        .accountsStrict({
+          pool: poolPda,
+          tickArray: getTickArrayPda(currentStart),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          config: configPda(),
+        })
+        .remainingAccounts([
+          { pubkey: getTickArrayPda(nextStart), isSigner: false, isWritable: false },
+        ])
+        .view();
+      return quote.ticksCrossed;
+    }
+
+    // a position too small to move the price, its two ticks sit on the path of the swaps below
+    async function openPathPosition() {
+      const pool = await program.account.pool.fetch(poolPda);
+      const base = Math.floor(pool.currentTick / TICK_SPACING) * TICK_SPACING;
+      const lower = base - 8 * TICK_SPACING;
+      const upper = base - 4 * TICK_SPACING;
+      const lowerStart = getTickArrayStartIndex(lower, TICK_SPACING);
+      const upperStart = getTickArrayStartIndex(upper, TICK_SPACING);
+      const [position] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(lower),
+          i32ToLeBytes(upper),
+        ],
+        program.programId
+      );
+      await program.methods
+        .openPosition(program.provider.wallet.publicKey, lower, upper, new anchor.BN(1), lowerStart, upperStart)
+        .accountsStrict({
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart),
+          upparTickArray: getTickArrayPda(upperStart),
+          position,
+          observationState: getObservationPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          payer: program.provider.wallet.publicKey,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+    }
+
+    before(async () => {
+      await setFeeBounds(300, 5_000);
+      await setDynamicFee(true);
+    });
+
+    after(async () => {
+      await setFeeBounds(300, 5_000);
+      await setDynamicFee(false);
+    });
+
+    it("needs the config to swap a dynamic fee pool", async () => {
+      await expectError(swap(true, null), /MissingConfig/);
+    });
+
+    it("raises the volatility accumulator and the fee by the ticks a swap crosses", async () => {
+      await openPathPosition();
+      // the accumulator counts initialized ticks crossed, not the 16 tick spacings moved
+      const crossedDown = await ticksCrossed(true);
+      assert.isAtLeast(crossedDown, 2);
+      await swap(true);
+      let pool = await program.account.pool.fetch(poolPda);
+      assert.equal(pool.volatilityAccumulator, crossedDown);
+      // the fee a swap pays is set before it moves the price
+      assert.equal(pool.feeRate, 300);
+
+      const crossedUp = await ticksCrossed(false);
+      await swap(false);
+      pool = await program.account.pool.fetch(poolPda);
+      // both swaps land within the filter period, so nothing decayed in between
+      assert.equal(pool.feeRate, 300 + crossedDown * 50);
+      assert.equal(pool.volatilityAccumulator, crossedDown + crossedUp);
+    });
+
+    it("applies new fee bounds on the next swap", async () => {
+      await setFeeBounds(300, 1_000);
+      await swap(true);
+      const pool = await program.account.pool.fetch(poolPda);
+      assert.equal(pool.feeRate, 1_000);
+    });
+  });
+
+  describe("limit orders", () => {
+    const ORDER_LIQUIDITY = new anchor.BN(1000);
+    const ARRAY_SPAN = TICKS_PER_ARRAY * TICK_SPACING;
+    const positionFor = (lower: number, upper: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(lower),
+          i32ToLeBytes(upper),
+        ],
+        program.programId
+      )[0];
+    const orderFor = (tick: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("limit_order"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(tick),
+        ],
+        program.programId
+      )[0];
+    const tokenBalances = async (): Promise<[bigint, bigint]> => {
+      const connection = program.provider.connection;
+      return [
+        (await getAccount(connection, userTokenAccount0)).amount,
+        (await getAccount(connection, userTokenAccount1)).amount,
+      ];
+    };
+    const currentTick = async () => (await program.account.pool.fetch(poolPda)).currentTick;
+
+    // first tick on the given side of the price whose order does not straddle two tick arrays
+    function orderTick(tick: number, sellToken0: boolean) {
+      let orderTick = sellToken0
+        ? Math.floor(tick / TICK_SPACING) * TICK_SPACING + TICK_SPACING
+        : Math.floor(tick / TICK_SPACING) * TICK_SPACING - TICK_SPACING;
+      if (getTickArrayStartIndex(orderTick, TICK_SPACING) !== getTickArrayStartIndex(orderTick + TICK_SPACING, TICK_SPACING)) {
+        orderTick += sellToken0 ? TICK_SPACING : -TICK_SPACING;
+      }
+      return orderTick;
+    }
+
+    function placeOrder(tick: number, sellToken0: boolean) {
+      const start = getTickArrayStartIndex(tick, TICK_SPACING);
+      return program.methods
+        .placeLimitOrder(tick, sellToken0, ORDER_LIQUIDITY, start)
+        .accountsStrict({
+          payer: program.provider.wallet.publicKey,
+          pool: poolPda,
+          tickArray: getTickArrayPda(start),
+          limitOrder: orderFor(tick),
+          observationState: getObservationPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+    }
+
+    function claimOrder(tick: number) {
+      return program.methods
+        .claimLimitOrder()
+        .accountsStrict({
+          observationState: getObservationPda(),
+          payer: program.provider.wallet.publicKey,
+          pool: poolPda,
+          tickArray: getTickArrayPda(getTickArrayStartIndex(tick, TICK_SPACING)),
+          limitOrder: orderFor(tick),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+    }
+
+    // every swap moves the price by 1000 ticks, so it never reaches past the next tick array
+    async function swapStep(zeroForOne: boolean) {
+      const currentStart = getTickArrayStartIndex(await currentTick(), TICK_SPACING);
+      return program.methods
+        .swap(new anchor.BN(1000), zeroForOne, new anchor.BN(0), 0)
+        .accountsStrict({
+          pool: poolPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tickArray: getTickArrayPda(currentStart),
+          observationState: getObservationPda(),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          payer: program.provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config: null,
+          referrerToken: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          {
+            pubkey: getTickArrayPda(currentStart + (zeroForOne ? -ARRAY_SPAN : ARRAY_SPAN)),
+            isSigner: false,
+            isWritable: true,
+          },
+        ])
+        .rpc();
+    }
+
+    before(async () => {
+      // liquidity around wherever the earlier tests left the price, so the swaps below go through
+      const lower = getTickArrayStartIndex(await currentTick(), TICK_SPACING) - 2 * ARRAY_SPAN;
+      const upper = lower + 5 * ARRAY_SPAN - TICK_SPACING;
+      const lowerStart = getTickArrayStartIndex(lower, TICK_SPACING);
+      const upperStart = getTickArrayStartIndex(upper, TICK_SPACING);
+      await program.methods
+        .openPosition(program.provider.wallet.publicKey, lower, upper, LIQUIDITY_AMOUNT, lowerStart, upperStart)
+        .accountsStrict({
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart),
+          upparTickArray: getTickArrayPda(upperStart),
+          position: positionFor(lower, upper),
+          observationState: getObservationPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          payer: program.provider.wallet.publicKey,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+    });
+
+    it("rejects an order on the wrong side of the price", async () => {
+      const tick = orderTick(await currentTick(), false);
+      await expectError(placeOrder(tick, true), /InvalidLimitOrder/);
+    });
+
+    it("cancels an order that was not filled", async () => {
+      const tick = orderTick(await currentTick(), false);
+      const [before0, before1] = await tokenBalances();
+      await placeOrder(tick, false);
+      const [placed0, placed1] = await tokenBalances();
+      assert.equal(placed0.toString(), before0.toString());
+      assert.equal((before1 - placed1).toString(), ORDER_LIQUIDITY.toString());
+
+      await claimOrder(tick);
+      const [after0, after1] = await tokenBalances();
+      assert.equal(after0.toString(), before0.toString());
+      assert.equal(after1.toString(), before1.toString());
+      assert.isNull(await program.provider.connection.getAccountInfo(orderFor(tick)));
+    });
+
+    it("fills an order once the price crosses it and keeps it filled when the price comes back", async () => {
+      const startTick = await currentTick();
+      const tick = orderTick(startTick, true);
+      await placeOrder(tick, true);
+
+      await swapStep(false);
+      assert.isTrue((await currentTick()) >= tick + TICK_SPACING, "the swap should cross the whole order");
+      await swapStep(true);
+      assert.isTrue((await currentTick()) < tick, "the price should be back below the order");
+
+      const [before0, before1] = await tokenBalances();
+      await claimOrder(tick);
+      const [after0, after1] = await tokenBalances();
+      assert.equal(after0.toString(), before0.toString());
+      assert.equal((after1 - before1).toString(), ORDER_LIQUIDITY.toString());
+
+      const tickArray = await program.account.tickArray.fetch(
+        getTickArrayPda(getTickArrayStartIndex(tick, TICK_SPACING))
+      );
+      const offset = (tick + TICK_SPACING - tickArray.startingTick) / TICK_SPACING;
+      assert.equal(tickArray.ticks[offset].limitOrderUnfilled0.toString(), "0");
+      assert.equal(tickArray.ticks[offset].limitOrderFilled0.toString(), "0");
+    });
+
+    it("fills an order selling token 1 once the price crosses it downward", async () => {
+      const startTick = await currentTick();
+      const tick = orderTick(startTick, false);
+      await placeOrder(tick, false);
+
+      await swapStep(true);
+      assert.isTrue((await currentTick()) < tick, "the swap should cross the whole order");
+      await swapStep(false);
+      assert.isTrue((await currentTick()) >= tick + TICK_SPACING, "the price should be back above the order");
+
+      const [before0, before1] = await tokenBalances();
+      await claimOrder(tick);
+      const [after0, after1] = await tokenBalances();
+      assert.equal((after0 - before0).toString(), ORDER_LIQUIDITY.toString());
+      assert.equal(after1.toString(), before1.toString());
+      assert.isNull(await program.provider.connection.getAccountInfo(orderFor(tick)));
+
+      // token 1 orders fill on their lower tick
+      const tickArray = await program.account.tickArray.fetch(
+        getTickArrayPda(getTickArrayStartIndex(tick, TICK_SPACING))
+      );
+      const offset = (tick - tickArray.startingTick) / TICK_SPACING;
+      assert.equal(tickArray.ticks[offset].limitOrderUnfilled1.toString(), "0");
+      assert.equal(tickArray.ticks[offset].limitOrderFilled1.toString(), "0");
+      const pool = await program.account.pool.fetch(poolPda);
+      assert.equal(pool.limitOrderLiquidity.toString(), "0");
+    });
+  });
+  describe("keeper settlement", () => {
+    const ARRAY_SPAN = TICKS_PER_ARRAY * TICK_SPACING;
+    const keeper = Keypair.generate();
+    let keeperToken0: PublicKey;
+    let keeperToken1: PublicKey;
+    let lower: number;
+    let upper: number;
+    const configPda = () =>
+      PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId)[0];
+    const positionFor = (lower: number, upper: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(lower),
+          i32ToLeBytes(upper),
+        ],
+        program.programId
+      )[0];
+    const currentTick = async () => (await program.account.pool.fetch(poolPda)).currentTick;
+
+    function settle(positionLower: number, positionUpper: number, keeperToken: PublicKey) {
+      return program.methods
+        .settleCrossedPosition()
+        .accountsStrict({
+          observationState: getObservationPda(),
+          keeper: keeper.publicKey,
+          owner: program.provider.wallet.publicKey,
+          config: configPda(),
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(getTickArrayStartIndex(positionLower, TICK_SPACING)),
+          upperTickArray: getTickArrayPda(getTickArrayStartIndex(positionUpper, TICK_SPACING)),
+          position: positionFor(positionLower, positionUpper),
+          ownerToken0: userTokenAccount0,
+          ownerToken1: userTokenAccount1,
+          keeperToken,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .signers([keeper])
+        .rpc();
+    }
+
+    function openPosition(positionLower: number, positionUpper: number) {
+      const lowerStart = getTickArrayStartIndex(positionLower, TICK_SPACING);
+      const upperStart = getTickArrayStartIndex(positionUpper, TICK_SPACING);
+      return program.methods
+        .openPosition(program.provider.wallet.publicKey, positionLower, positionUpper, LIQUIDITY_AMOUNT, lowerStart, upperStart)
+        .accountsStrict({
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart),
+          upparTickArray: getTickArrayPda(upperStart),
+          position: positionFor(positionLower, positionUpper),
+          observationState: getObservationPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          payer: program.provider.wallet.publicKey,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+    }
+
+    // the wide position from the limit order tests keeps the pool liquid
+    async function swapStep(zeroForOne: boolean) {
+      const currentStart = getTickArrayStartIndex(await currentTick(), TICK_SPACING);
+      return program.methods
+        .swap(new anchor.BN(1000), zeroForOne, new anchor.BN(0), 0)
+        .accountsStrict({
+          pool: poolPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tickArray: getTickArrayPda(currentStart),
+          observationState: getObservationPda(),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          payer: program.provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config: null,
+          referrerToken: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          {
+            pubkey: getTickArrayPda(currentStart + (zeroForOne ? -ARRAY_SPAN : ARRAY_SPAN)),
+            isSigner: false,
+            isWritable: true,
+          },
+        ])
+        .rpc();
+    }
+
+    before(async () => {
+      const connection = program.provider.connection;
+      const payer = program.provider.wallet.payer;
+      keeperToken0 = await createAssociatedTokenAccount(connection, payer, tokenMint0, keeper.publicKey);
+      keeperToken1 = await createAssociatedTokenAccount(connection, payer, tokenMint1, keeper.publicKey);
+      // the keeper pays for the owner's token account if it has to be created
+      await connection.confirmTransaction(
+        await connection.requestAirdrop(keeper.publicKey, anchor.web3.LAMPORTS_PER_SOL),
+        "confirmed"
+      );
+
+      // a take-profit position ending on the next tick array boundary above the price, so both
+      // of its ticks live in different arrays
+      upper = getTickArrayStartIndex(await currentTick(), TICK_SPACING) + ARRAY_SPAN;
+      lower = upper - TICK_SPACING;
+      await openPosition(lower, upper);
+    });
+
+    it("only lets the owner opt a position in", async () => {
+      await expectError(
+        program.methods
+          .setSettleOnCross(1)
+          .accountsStrict({ owner: keeper.publicKey, position: positionFor(lower, upper) })
+          .signers([keeper])
+          .rpc(),
+        /Unauthorized/
+      );
+    });
+
+    it("refuses to settle a position the price has not gone past", async () => {
+      await program.methods
+        .setSettleOnCross(1)
+        .accountsStrict({ owner: program.provider.wallet.publicKey, position: positionFor(lower, upper) })
+        .rpc();
+
+      await expectError(settle(lower, upper, keeperToken1), /PositionNotCrossed/);
+    });
+
+    it("rejects a keeper bounty above MAX_KEEPER_BOUNTY_BPS", async () => {
+      await expectError(
+        program.methods
+          .setKeeperBounty(1_001)
+          .accountsStrict({ admin: newAdmin.publicKey, config: configPda() })
+          .signers([newAdmin])
+          .rpc(),
+        /InvalidKeeperBounty/
+      );
+    });
+
+    it("settles into the owner's account once crossed and pays the keeper", async () => {
+      while ((await currentTick()) < upper) {
+        await swapStep(false);
+      }
+
+      const connection = program.provider.connection;
+      const before0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const before1 = (await getAccount(connection, userTokenAccount1)).amount;
+      await settle(lower, upper, keeperToken1);
+      const after0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const after1 = (await getAccount(connection, userTokenAccount1)).amount;
+
+      // 0.1% default bounty out of the 100_000 token 1 the position turned into, plus any fees
+      // it earned while the price went through it
+      assert.equal((await getAccount(connection, keeperToken1)).amount.toString(), "100");
+      assert.isTrue(after1 - before1 >= 99_900n);
+      assert.equal(after0.toString(), before0.toString());
+      assert.isNull(await connection.getAccountInfo(positionFor(lower, upper)));
+    });
+
+    it("settles a stop-loss position once the price falls below it", async () => {
+      // token 1 only, the array boundary at or below the price is its upper tick
+      const stopUpper = getTickArrayStartIndex(await currentTick(), TICK_SPACING);
+      const stopLower = stopUpper - TICK_SPACING;
+      await openPosition(stopLower, stopUpper);
+      await program.methods
+        .setSettleOnCross(2)
+        .accountsStrict({ owner: program.provider.wallet.publicKey, position: positionFor(stopLower, stopUpper) })
+        .rpc();
+      await expectError(settle(stopLower, stopUpper, keeperToken0), /PositionNotCrossed/);
+
+      while ((await currentTick()) >= stopLower) {
+        await swapStep(true);
+      }
+
+      const connection = program.provider.connection;
+      const before0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const before1 = (await getAccount(connection, userTokenAccount1)).amount;
+      await settle(stopLower, stopUpper, keeperToken0);
+      const after0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const after1 = (await getAccount(connection, userTokenAccount1)).amount;
+
+      // the 100_000 token 1 were sold into 100_000 token 0 on the way down, the bounty is paid in
+      // token 0 and the fees it earned are token 0 as well
+      assert.equal((await getAccount(connection, keeperToken0)).amount.toString(), "100");
+      assert.isTrue(after0 - before0 >= 99_900n);
+      assert.equal(after1.toString(), before1.toString());
+      assert.isNull(await connection.getAccountInfo(positionFor(stopLower, stopUpper)));
+    });
+  });
+
+  describe("zap in and zap out", () => {
+    const ARRAY_SPAN = TICKS_PER_ARRAY * TICK_SPACING;
+    let lower: number;
+    let upper: number;
+    const positionFor = (lower: number, upper: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(lower),
+          i32ToLeBytes(upper),
+        ],
+        program.programId
+      )[0];
+
+    async function zapIn(amountIn: number, minLiquidity: number) {
+      const currentStart = getTickArrayStartIndex(
+        (await program.account.pool.fetch(poolPda)).currentTick,
+        TICK_SPACING
+      );
+      const lowerStart = getTickArrayStartIndex(lower, TICK_SPACING);
+      const upperStart = getTickArrayStartIndex(upper, TICK_SPACING);
+      return program.methods
+        .zapIn(new anchor.BN(amountIn), true, lower, upper, new anchor.BN(minLiquidity), lowerStart, upperStart)
+        .accountsStrict({
+          payer: program.provider.wallet.publicKey,
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart),
+          upparTickArray: getTickArrayPda(upperStart),
+          position: positionFor(lower, upper),
+          observationState: getObservationPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          { pubkey: getTickArrayPda(currentStart), isSigner: false, isWritable: true },
+          { pubkey: getTickArrayPda(currentStart - ARRAY_SPAN), isSigner: false, isWritable: true },
+        ])
+        .rpc();
+    }
+
+    // the whole position unless liquidity is given
+    async function zapOut(minimumOut: number, close: boolean, liquidity?: anchor.BN) {
+      const pool = await program.account.pool.fetch(poolPda);
+      const currentStart = getTickArrayStartIndex(pool.currentTick, TICK_SPACING);
+      const position = await program.account.position.fetch(positionFor(lower, upper));
+      return program.methods
+        .zapOut(liquidity ?? position.liquidity, true, new anchor.BN(minimumOut), close)
+        .accountsStrict({
+          payer: program.provider.wallet.publicKey,
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(getTickArrayStartIndex(lower, TICK_SPACING)),
+          upparTickArray: getTickArrayPda(getTickArrayStartIndex(upper, TICK_SPACING)),
+          position: positionFor(lower, upper),
+          observationState: getObservationPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          { pubkey: getTickArrayPda(currentStart), isSigner: false, isWritable: true },
+          { pubkey: getTickArrayPda(currentStart + ARRAY_SPAN), isSigner: false, isWritable: true },
+        ])
+        .rpc();
+    }
+
+    before(async () => {
+      // wide enough that the price stays inside it after the zap's own swap
+      const currentStart = getTickArrayStartIndex(
+        (await program.account.pool.fetch(poolPda)).currentTick,
+        TICK_SPACING
+      );
+      lower = currentStart - 2 * ARRAY_SPAN;
+      upper = currentStart + 2 * ARRAY_SPAN;
+    });
+
+    it("rejects a zap that would mint less than the minimum liquidity", async () => {
+      await expectError(zapIn(100_000, 100_000), /SlippageExceeded/);
+    });
+
+    it("swaps about half of a single token input and deposits both sides", async () => {
+      const connection = program.provider.connection;
+      const before0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const before1 = (await getAccount(connection, userTokenAccount1)).amount;
+      const vaultBefore0 = (await getAccount(connection, tokenVault0)).amount;
+      const vaultBefore1 = (await getAccount(connection, tokenVault1)).amount;
+      await zapIn(100_000, 99_000);
+      const after0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const after1 = (await getAccount(connection, userTokenAccount1)).amount;
+
+      // 50_025 token 0 swap into 50_025 - 50 fee = 49_975 token 1, which pairs with the 49_975
+      // token 0 left at the 1:1 the range takes inside it, so there is no dust on either side
+      const position = await program.account.position.fetch(positionFor(lower, upper));
+      assert.equal(position.liquidity.toString(), "99950");
+      assert.equal((before0 - after0).toString(), "100000");
+      assert.equal(after1.toString(), before1.toString());
+      // the swapped token 1 never leaves the pool, it is deposited straight back
+      assert.equal(((await getAccount(connection, tokenVault0)).amount - vaultBefore0).toString(), "100000");
+      assert.equal((await getAccount(connection, tokenVault1)).amount.toString(), vaultBefore1.toString());
+    });
+
+    it("rejects a zap out below the minimum output", async () => {
+      await expectError(zapOut(1_000_000, true), /SlippageExceeded/);
+    });
+
+    // in range the range holds liquidity / 2 of each token, the token 1 half is swapped at a 0.1% fee
+    const zappedOut = (amount0: bigint, amount1: bigint) => amount0 + amount1 - (amount1 * 1_000n) / 1_000_000n;
+
+    it("zaps part of the position out and leaves the fees it is owed on it", async () => {
+      const connection = program.provider.connection;
+      // the first zap out's own swap pays fees to what is left, the second one moves them into tokens_owed
+      await zapOut(0, false, new anchor.BN(40_000));
+      const before0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const before1 = (await getAccount(connection, userTokenAccount1)).amount;
+      await zapOut(0, false, new anchor.BN(20_000));
+      const after0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const after1 = (await getAccount(connection, userTokenAccount1)).amount;
+
+      const position = await program.account.position.fetch(positionFor(lower, upper));
+      assert.equal(position.liquidity.toString(), (99_950 - 40_000 - 20_000).toString());
+      assert.isTrue(position.tokensOwed1.gtn(0), "the swap fees should be owed to the position");
+      // only the withdrawn liquidity is paid out, the owed fees stay for collect_fees or the close
+      assert.equal((after0 - before0).toString(), zappedOut(10_000n, 10_000n).toString());
+      assert.equal(after1.toString(), before1.toString());
+    });
+
+    it("closes the position into token 0 only", async () => {
+      const connection = program.provider.connection;
+      const position = await program.account.position.fetch(positionFor(lower, upper));
+      const half = BigInt(position.liquidity.toString()) / 2n;
+      const owed0 = BigInt(position.tokensOwed0.toString());
+      const owed1 = BigInt(position.tokensOwed1.toString());
+      const before0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const before1 = (await getAccount(connection, userTokenAccount1)).amount;
+      await zapOut(0, true);
+      const after0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const after1 = (await getAccount(connection, userTokenAccount1)).amount;
+
+      // both halves and the owed fees come back as token 0, the close settles the fees earned
+      // since the last zap out on top
+      assert.isTrue(after0 - before0 >= zappedOut(half + owed0, half + owed1));
+      assert.equal(after1.toString(), before1.toString());
+      assert.isNull(await connection.getAccountInfo(positionFor(lower, upper)));
+    });
+
+    it("zaps into a range that takes almost only token 1 with little dust", async () => {
+      const connection = program.provider.connection;
+      const aligned = Math.floor((await program.account.pool.fetch(poolPda)).currentTick / TICK_SPACING) * TICK_SPACING;
+      // the price sits two spacings below the top of a range reaching four arrays down,
+      // so the range takes token 1 to token 0 at roughly 98 to 2
+      lower = aligned - 4 * ARRAY_SPAN;
+      upper = aligned + 2 * TICK_SPACING;
+      const before0 = (await getAccount(connection, userTokenAccount0)).amount;
+      const before1 = (await getAccount(connection, userTokenAccount1)).amount;
+      await zapIn(100_000, 1);
+      const dust0 = (await getAccount(connection, userTokenAccount0)).amount - (before0 - 100_000n);
+      const dust1 = (await getAccount(connection, userTokenAccount1)).amount - before1;
+
+      // the search lands on the smallest swap whose output covers what the rest of the input
+      // pairs with, so well under 1% of the input comes back on either side
+      const position = await program.account.position.fetch(positionFor(lower, upper));
+      assert.isTrue(position.liquidity.gtn(0));
+      assert.isTrue(dust0 < 1_000n, `token 0 dust ${dust0}`);
+      assert.isTrue(dust1 < 1_000n, `token 1 dust ${dust1}`);
+    });
+  });
+  describe("tick array rent", () => {
+    const ARRAY_SPAN = TICKS_PER_ARRAY * TICK_SPACING;
+    // far below anything the other tests touch, so both arrays are created and emptied here
+    const lower = -40 * ARRAY_SPAN;
+    const upper = lower + ARRAY_SPAN;
+    const lowerStart = getTickArrayStartIndex(lower, TICK_SPACING);
+    const upperStart = getTickArrayStartIndex(upper, TICK_SPACING);
+    const cranker = Keypair.generate();
+    const position = () =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(lower),
+          i32ToLeBytes(upper),
+        ],
+        program.programId
+      )[0];
+
+    // anyone can crank the close, the rent still goes to whoever created the array
+    function closeTickArray(startIndex: number) {
+      return program.methods
+        .closeTickArray(startIndex)
+        .accountsStrict({
+          payer: cranker.publicKey,
+          funder: program.provider.wallet.publicKey,
+          pool: poolPda,
+          tickArray: getTickArrayPda(startIndex),
+        })
+        .signers([cranker])
+        .rpc();
+    }
+
+    before(async () => {
+      const sig = await program.provider.connection.requestAirdrop(cranker.publicKey, 1_000_000_000);
+      await program.provider.connection.confirmTransaction(sig);
+
+      await program.methods
+        .openPosition(program.provider.wallet.publicKey, lower, upper, LIQUIDITY_AMOUNT, lowerStart, upperStart)
+        .accountsStrict({
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart),
+          upparTickArray: getTickArrayPda(upperStart),
+          position: position(),
+          observationState: getObservationPda(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          payer: program.provider.wallet.publicKey,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+    });
+
+    it("refuses to close an array a position still points at", async () => {
+      await expectError(closeTickArray(lowerStart), /TickArrayNotEmpty/);
+    });
+
+    it("flips a tick back to uninitialized once its last position is gone", async () => {
+      // a second position one tick spacing inside the first, in the same two arrays
+      const innerLower = lower + TICK_SPACING;
+      const innerUpper = upper + TICK_SPACING;
+      const innerPosition = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(innerLower),
+          i32ToLeBytes(innerUpper),
+        ],
+        program.programId
+      )[0];
+      const tickInfo = async (tick: number) => {
+        const start = getTickArrayStartIndex(tick, TICK_SPACING);
+        const tickArray = await program.account.tickArray.fetch(getTickArrayPda(start));
+        return tickArray.ticks[(tick - start) / TICK_SPACING];
+      };
+      const accounts = {
+        pool: poolPda,
+        lowerTickArray: getTickArrayPda(lowerStart),
+        position: innerPosition,
+        userToken0: userTokenAccount0,
+        userToken1: userTokenAccount1,
+        poolToken0: tokenVault0,
+        poolToken1: tokenVault1,
+        tokenMint0: tokenMint0,
+        tokenMint1: tokenMint1,
+        systemProgram: SystemProgram.programId,
+        tokenProgram0: TOKEN_PROGRAM_ID,
+        tokenProgram1: TOKEN_PROGRAM_ID,
+        ...eventAccounts(),
+      };
+
+      await program.methods
+        .openPosition(program.provider.wallet.publicKey, innerLower, innerUpper, LIQUIDITY_AMOUNT, lowerStart, upperStart)
+        .accountsStrict({
+          ...accounts,
+          upparTickArray: getTickArrayPda(upperStart),
+          observationState: getObservationPda(),
+          payer: program.provider.wallet.publicKey,
+        })
+        .rpc();
+      assert.equal((await tickInfo(innerLower)).initialized, 1);
+      assert.equal((await tickInfo(innerUpper)).initialized, 1);
+
+      await program.methods
+        .closePosition(innerLower, innerUpper, lowerStart, upperStart)
+        .accountsStrict({
+          observationState: getObservationPda(),
+          ...accounts,
+          upperTickArray: getTickArrayPda(upperStart),
+          owner: program.provider.wallet.publicKey,
+        })
+        .rpc();
+      for (const tick of [innerLower, innerUpper]) {
+        const info = await tickInfo(tick);
+        assert.equal(info.initialized, 0);
+        assert.equal(info.liquidityGross.toString(), "0");
+        assert.equal(info.liquidityNet.toString(), "0");
+      }
+      // the first position's ticks are untouched
+      assert.equal((await tickInfo(lower)).initialized, 1);
+      assert.equal((await tickInfo(upper)).initialized, 1);
+    });
+
+    it("rejects more liquidity on a tick than max_liquidity_per_tick", async () => {
+      const pool = await program.account.pool.fetch(poolPda);
+      await expectError(
+        program.methods
+          .openPosition(
+            program.provider.wallet.publicKey,
+            lower,
+            upper,
+            pool.maxLiquidityPerTick.addn(1),
+            lowerStart,
+            upperStart
+          )
+          .accountsStrict({
+            pool: poolPda,
+            lowerTickArray: getTickArrayPda(lowerStart),
+            upparTickArray: getTickArrayPda(upperStart),
+            position: position(),
+            observationState: getObservationPda(),
+            userToken0: userTokenAccount0,
+            userToken1: userTokenAccount1,
+            poolToken0: tokenVault0,
+            poolToken1: tokenVault1,
+            payer: program.provider.wallet.publicKey,
+            tokenMint0: tokenMint0,
+            tokenMint1: tokenMint1,
+            systemProgram: SystemProgram.programId,
+            tokenProgram0: TOKEN_PROGRAM_ID,
+            tokenProgram1: TOKEN_PROGRAM_ID,
+            ...eventAccounts(),
+          })
+          .rpc(),
+        /LiquidityPerTickExceeded/
+      );
+    });
+
+    it("refunds the rent to the funder once the array is empty", async () => {
+      await program.methods
+        .closePosition(lower, upper, lowerStart, upperStart)
+        .accountsStrict({
+          observationState: getObservationPda(),
+          owner: program.provider.wallet.publicKey,
+          pool: poolPda,
+          lowerTickArray: getTickArrayPda(lowerStart),
+          upperTickArray: getTickArrayPda(upperStart),
+          position: position(),
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          ...eventAccounts(),
+        })
+        .rpc();
+
+      const connection = program.provider.connection;
+      for (const startIndex of [lowerStart, upperStart]) {
+        const rent = (await connection.getAccountInfo(getTickArrayPda(startIndex))).lamports;
+        const before = await connection.getBalance(program.provider.wallet.publicKey);
+        await closeTickArray(startIndex);
+        const after = await connection.getBalance(program.provider.wallet.publicKey);
+
+        assert.equal(after - before, rent);
+        assert.isNull(await connection.getAccountInfo(getTickArrayPda(startIndex)));
+      }
+    });
+  });
+  describe("liquidity outside the current price", () => {
+    const ARRAY_SPAN = TICKS_PER_ARRAY * TICK_SPACING;
+    const positionFor = (lower: number, upper: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("position"),
+          program.provider.wallet.publicKey.toBuffer(),
+          poolPda.toBuffer(),
+          i32ToLeBytes(lower),
+          i32ToLeBytes(upper),
+        ],
+        program.programId
+      )[0];
+    const currentTick = async () => (await program.account.pool.fetch(poolPda)).currentTick;
+    const balances = async (): Promise<[bigint, bigint]> => {
+      const connection = program.provider.connection;
+      return [
+        (await getAccount(connection, userTokenAccount0)).amount,
+        (await getAccount(connection, userTokenAccount1)).amount,
+      ];
+    };
+    const positionAccounts = (lower: number, upper: number) => ({
+      pool: poolPda,
+      lowerTickArray: getTickArrayPda(getTickArrayStartIndex(lower, TICK_SPACING)),
+      position: positionFor(lower, upper),
+      userToken0: userTokenAccount0,
+      userToken1: userTokenAccount1,
+      poolToken0: tokenVault0,
+      poolToken1: tokenVault1,
+      tokenMint0: tokenMint0,
+      tokenMint1: tokenMint1,
+      systemProgram: SystemProgram.programId,
+      tokenProgram0: TOKEN_PROGRAM_ID,
+      tokenProgram1: TOKEN_PROGRAM_ID,
+      ...eventAccounts(),
+    });
+
+    function openPosition(lower: number, upper: number, liquidity: anchor.BN) {
+      const lowerStart = getTickArrayStartIndex(lower, TICK_SPACING);
+      const upperStart = getTickArrayStartIndex(upper, TICK_SPACING);
+      return program.methods
+        .openPosition(program.provider.wallet.publicKey, lower, upper, liquidity, lowerStart, upperStart)
+        .accountsStrict({
+          ...positionAccounts(lower, upper),
+          upparTickArray: getTickArrayPda(upperStart),
+          observationState: getObservationPda(),
+          payer: program.provider.wallet.publicKey,
+        })
+        .rpc();
+    }
+
+    describe("a position above the price", () => {
+      let lower: number;
+      let upper: number;
+
+      before(async () => {
+        lower = getTickArrayStartIndex(await currentTick(), TICK_SPACING) + 2 * ARRAY_SPAN;
+        upper = lower + ARRAY_SPAN;
+      });
+
+      it("opens with token 0 only and leaves the active liquidity alone", async () => {
+        const liquidityBefore = (await program.account.pool.fetch(poolPda)).globalLiquidity;
+        const [before0, before1] = await balances();
+        await openPosition(lower, upper, LIQUIDITY_AMOUNT);
+        const [after0, after1] = await balances();
+
+        assert.equal((before0 - after0).toString(), LIQUIDITY_AMOUNT.toString());
+        assert.equal(after1.toString(), before1.toString());
+        const pool = await program.account.pool.fetch(poolPda);
+        assert.equal(pool.globalLiquidity.toString(), liquidityBefore.toString());
+      });
+
+      it("decreases into token 0 only", async () => {
+        const liquidityBefore = (await program.account.pool.fetch(poolPda)).globalLiquidity;
+        const [before0, before1] = await balances();
+        await program.methods
+          .decreaseLiquidity(LIQUIDITY_AMOUNT.divn(2), lower, upper)
+          .accountsStrict({
+            observationState: getObservationPda(),
+            ...positionAccounts(lower, upper),
+            upparTickArray: getTickArrayPda(getTickArrayStartIndex(upper, TICK_SPACING)),
+            payer: program.provider.wallet.publicKey,
+          })
+          .rpc();
+        const [after0, after1] = await balances();
+
+        assert.equal((after0 - before0).toString(), LIQUIDITY_AMOUNT.divn(2).toString());
+        assert.equal(after1.toString(), before1.toString());
+        const pool = await program.account.pool.fetch(poolPda);
+        assert.equal(pool.globalLiquidity.toString(), liquidityBefore.toString());
+      });
+
+      it("closes into token 0 only", async () => {
+        const liquidityBefore = (await program.account.pool.fetch(poolPda)).globalLiquidity;
+        const [before0, before1] = await balances();
+        await program.methods
+          .closePosition(
+            lower,
+            upper,
+            getTickArrayStartIndex(lower, TICK_SPACING),
+            getTickArrayStartIndex(upper, TICK_SPACING)
+          )
+          .accountsStrict({
+            observationState: getObservationPda(),
+            ...positionAccounts(lower, upper),
+            upperTickArray: getTickArrayPda(getTickArrayStartIndex(upper, TICK_SPACING)),
+            owner: program.provider.wallet.publicKey,
+          })
+          .rpc();
+        const [after0, after1] = await balances();
+
+        assert.equal((after0 - before0).toString(), LIQUIDITY_AMOUNT.divn(2).toString());
+        assert.equal(after1.toString(), before1.toString());
+        assert.isNull(await program.provider.connection.getAccountInfo(positionFor(lower, upper)));
+        const pool = await program.account.pool.fetch(poolPda);
+        assert.equal(pool.globalLiquidity.toString(), liquidityBefore.toString());
+      });
+    });
+
+    it("swaps down through a range holding more liquidity than the one around it", async () => {
+      const tick = await currentTick();
+      // every swap moves the price 1000 ticks, the nested range sits inside that step and inside
+      // a single tick array, so the same swap crosses its upper and its lower tick
+      const nestedStart = getTickArrayStartIndex(tick - 500, TICK_SPACING);
+      const nestedLower =
+        Math.ceil(Math.max(tick - 999, nestedStart + TICK_SPACING) / TICK_SPACING) * TICK_SPACING;
+      const nestedUpper =
+        Math.floor(Math.min(tick, nestedStart + ARRAY_SPAN - TICK_SPACING) / TICK_SPACING) * TICK_SPACING;
+
+      // the surrounding range also creates the array the nested one lives in
+      await openPosition(nestedStart, nestedStart + 3 * ARRAY_SPAN, LIQUIDITY_AMOUNT);
+      const surrounding = (await program.account.pool.fetch(poolPda)).globalLiquidity;
+      await openPosition(nestedLower, nestedUpper, surrounding.muln(10));
+
+      const currentStart = getTickArrayStartIndex(tick, TICK_SPACING);
+      await program.methods
+        .swap(new anchor.BN(1000), true, new anchor.BN(0), 0)
+        .accountsStrict({
+          pool: poolPda,
+          userToken0: userTokenAccount0,
+          userToken1: userTokenAccount1,
+          poolToken0: tokenVault0,
+          poolToken1: tokenVault1,
+          tickArray: getTickArrayPda(currentStart),
+          observationState: getObservationPda(),
+          tokenMint0: tokenMint0,
+          tokenMint1: tokenMint1,
+          payer: program.provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: TOKEN_PROGRAM_ID,
+          tokenProgram1: TOKEN_PROGRAM_ID,
+          config: null,
+          referrerToken: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          { pubkey: getTickArrayPda(currentStart - ARRAY_SPAN), isSigner: false, isWritable: true },
+        ])
+        .rpc();
+
+      assert.equal(await currentTick(), tick - 1000);
+      const nestedArray = await program.account.tickArray.fetch(getTickArrayPda(nestedStart));
+      const lowerInfo = nestedArray.ticks[(nestedLower - nestedStart) / TICK_SPACING];
+      const upperInfo = nestedArray.ticks[(nestedUpper - nestedStart) / TICK_SPACING];
+      // both ticks of the nested range were written, not just the one applied last
+      assert.equal(lowerInfo.liquidityNet.toString(), surrounding.muln(10).toString());
+      assert.equal(upperInfo.liquidityNet.toString(), surrounding.muln(10).neg().toString());
+    });
+
+    it("writes an observation before the liquidity changes", async () => {
+      const tick = await currentTick();
+      const lower = getTickArrayStartIndex(tick, TICK_SPACING);
+      // observations are written at most once a second
+      await new Promise((resolve) => setTimeout(resolve, 1100));
+      const before = await program.account.observationState.fetch(getObservationPda());
+      const last = await fetchObservation(before.observationIndex);
+
+      await openPosition(lower, lower + ARRAY_SPAN, LIQUIDITY_AMOUNT);
+
+      const after = await program.account.observationState.fetch(getObservationPda());
+      assert.equal(after.observationIndex, (before.observationIndex + 1) % before.observationCardinality);
+      const written = await fetchObservation(after.observationIndex);
+      const elapsed = written.blockTimestamp - last.blockTimestamp;
+      assert.isAbove(elapsed, 0);
+      // the interval up to the deposit is weighted with the tick from before it
+      assert.equal(written.tickCumulative.toString(), last.tickCumulative.addn(tick * elapsed).toString());
+    });
+  });
+
+  describe("token-2022 mints", () => {
+    const TRANSFER_FEE_BPS = 100;
+    const SPAN = TICKS_PER_ARRAY * TICK_SPACING;
+    // the upper tick sits in the array holding the current tick, so the swap finds it created
+    const LOWER = -SPAN;
+    const UPPER = SPAN - TICK_SPACING;
+
+    let legacyMint: PublicKey;
+    let feeMint: PublicKey;
+    let mints: [PublicKey, PublicKey];
+    let programs: [PublicKey, PublicKey];
+    let users: [PublicKey, PublicKey];
+    let pool: PublicKey;
+
+    const pda = (seeds: Buffer[]) => PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+    const vaultFor = (mint: PublicKey) => pda([Buffer.from("vault"), pool.toBuffer(), mint.toBuffer()]);
+    const tickArrayFor = (startIndex: number) =>
+      pda([Buffer.from("tick_array"), pool.toBuffer(), i32ToLeBytes(startIndex)]);
+
+    async function createMint2022(extension: ExtensionType, initializeExtension: (mint: PublicKey) => anchor.web3.TransactionInstruction) {
+      const connection = program.provider.connection;
+      const wallet = program.provider.wallet.publicKey;
+      const mint = Keypair.generate();
+      const space = getMintLen([extension]);
+      const transaction = new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: wallet,
+          newAccountPubkey: mint.publicKey,
+          space,
+          lamports: await connection.getMinimumBalanceForRentExemption(space),
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        initializeExtension(mint.publicKey),
+        createInitializeMintInstruction(mint.publicKey, 6, wallet, null, TOKEN_2022_PROGRAM_ID)
+      );
+      await sendAndConfirmTransaction(connection, transaction, [program.provider.wallet.payer, mint]);
+      return mint.publicKey;
+    }
+
+    // mints sorted the way the pool wants them, each with the program that owns it
+    function canonical(legacy: PublicKey, token2022: PublicKey): [[PublicKey, PublicKey], [PublicKey, PublicKey]] {
+      const { mint0, mint1, swapped } = canonicalMintOrder(legacy, token2022, INITIAL_SQRT_PRICE);
+      return [
+        [mint0, mint1],
+        swapped ? [TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID] : [TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID],
+      ];
+    }
+
+    before(async () => {
+      const connection = program.provider.connection;
+      const payer = program.provider.wallet.payer;
+      const wallet = program.provider.wallet.publicKey;
+
+      legacyMint = await createMint(connection, payer, wallet, null, 6);
+      feeMint = await createMint2022(ExtensionType.TransferFeeConfig, (mint) =>
+        createInitializeTransferFeeConfigInstruction(
+          mint,
+          wallet,
+          wallet,
+          TRANSFER_FEE_BPS,
+          BigInt(1_000_000_000),
+          TOKEN_2022_PROGRAM_ID
+        )
+      );
+      [mints, programs] = canonical(legacyMint, feeMint);
+      pool = pda([Buffer.from("pool"), mints[0].toBuffer(), mints[1].toBuffer(), i32ToLeBytes(TICK_SPACING)]);
+
+      users = [
+        await createAssociatedTokenAccount(connection, payer, mints[0], wallet, undefined, programs[0]),
+        await createAssociatedTokenAccount(connection, payer, mints[1], wallet, undefined, programs[1]),
+      ];
+      for (const i of [0, 1]) {
+        await mintTo(connection, payer, mints[i], users[i], wallet, 1_000_000_000, [], undefined, programs[i]);
+      }
+    });
+
+    it("pairs an SPL Token mint with a Token-2022 mint", async () => {
+      await initalisePoolWith(mints[0], mints[1], programs[0], programs[1]);
+
+      const positionPda = pda([
+        Buffer.from("position"),
+        program.provider.wallet.publicKey.toBuffer(),
+        pool.toBuffer(),
+        i32ToLeBytes(LOWER),
+        i32ToLeBytes(UPPER),
+      ]);
+      await program.methods
+        .openPosition(program.provider.wallet.publicKey, LOWER, UPPER, LIQUIDITY_AMOUNT, -SPAN, 0)
+        .accountsStrict({
+          pool,
+          lowerTickArray: tickArrayFor(-SPAN),
+          upparTickArray: tickArrayFor(0),
+          position: positionPda,
+          observationState: pda([Buffer.from("observation"), pool.toBuffer()]),
+          userToken0: users[0],
+          userToken1: users[1],
+          poolToken0: vaultFor(mints[0]),
+          poolToken1: vaultFor(mints[1]),
+          payer: program.provider.wallet.publicKey,
+          tokenMint0: mints[0],
+          tokenMint1: mints[1],
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: programs[0],
+          tokenProgram1: programs[1],
+          ...eventAccounts(),
+        })
+        .rpc();
+
+      const position = await program.account.position.fetch(positionPda);
+      assert.equal(position.liquidity.toString(), LIQUIDITY_AMOUNT.toString());
+    });
+
+    it("swaps in a transfer fee mint and only counts what reaches the vault", async () => {
+      const connection = program.provider.connection;
+      const amountIn = 10_000;
+      const transferFee = (amountIn * TRANSFER_FEE_BPS) / 10_000;
+      // sell the transfer fee mint
+      const zeroForOne = mints[0].equals(feeMint);
+      const [inIndex, outIndex] = zeroForOne ? [0, 1] : [1, 0];
+      const amount = async (account: PublicKey, i: number) =>
+        (await getAccount(connection, account, undefined, programs[i])).amount;
+
+      const vaultInBefore = await amount(vaultFor(mints[inIndex]), inIndex);
+      const vaultOutBefore = await amount(vaultFor(mints[outIndex]), outIndex);
+      const userOutBefore = await amount(users[outIndex], outIndex);
+
+      await program.methods
+        .swap(new anchor.BN(amountIn), zeroForOne, new anchor.BN(0), 0)
+        .accountsStrict({
+          pool,
+          userToken0: users[0],
+          userToken1: users[1],
+          poolToken0: vaultFor(mints[0]),
+          poolToken1: vaultFor(mints[1]),
+          tickArray: tickArrayFor(0),
+          observationState: pda([Buffer.from("observation"), pool.toBuffer()]),
+          tokenMint0: mints[0],
+          tokenMint1: mints[1],
+          payer: program.provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenProgram0: programs[0],
+          tokenProgram1: programs[1],
+          config: null,
+          referrerToken: null,
+          ...eventAccounts(),
+        })
+        .remainingAccounts([
+          { pubkey: tickArrayFor(zeroForOne ? -SPAN : SPAN), isSigner: false, isWritable: true },
+        ])
+        .rpc();
+
+      const vaultInDelta = (await amount(vaultFor(mints[inIndex]), inIndex)) - vaultInBefore;
+      const vaultOutDelta = vaultOutBefore - (await amount(vaultFor(mints[outIndex]), outIndex));
+      const userOutDelta = (await amount(users[outIndex], outIndex)) - userOutBefore;
+      assert.equal(vaultInDelta, BigInt(amountIn - transferFee));
+      assert.isTrue(vaultOutDelta > 0n);
+      // the output mint has no fee, the user gets all the vault paid
+      assert.equal(userOutDelta, vaultOutDelta);
+    });
+
+    it("rejects a mint with a permanent delegate", async () => {
+      const wallet = program.provider.wallet.publicKey;
+      const delegateMint = await createMint2022(ExtensionType.PermanentDelegate, (mint) =>
+        createInitializePermanentDelegateInstruction(mint, wallet, TOKEN_2022_PROGRAM_ID)
+      );
+      const [pair, pairPrograms] = canonical(legacyMint, delegateMint);
+
+      await expectError(
+        initalisePoolWith(pair[0], pair[1], pairPrograms[0], pairPrograms[1]),
+        /UnsupportedMintExtension/
+      );
+    });
+  });
+});